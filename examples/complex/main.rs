@@ -10,13 +10,16 @@ async fn main() {
     // OPENAI_API_KEY=your_api_key
     dotenv().ok();
 
-    let llm_engine = OpenAIBackend::new(var("OPENAI_API_KEY").unwrap());
-    let memory_engine = MemoryEngine::new(var("DATABASE_URL").unwrap()).await;
+    let llm_engine = OpenAIBackend::new(var("OPENAI_API_KEY").unwrap()).unwrap();
+    let memory_engine = MemoryEngine::new(var("DATABASE_URL").unwrap())
+        .await
+        .unwrap();
 
     let mut agent = AgentBuilder::new()
         .name("AI (Agent)".to_string())
         .build(llm_engine, memory_engine)
-        .await;
+        .await
+        .unwrap();
 
     let joe = agent
         .init_interaction(
@@ -24,7 +27,8 @@ async fn main() {
             "A talkative chatbot conversation".to_string(),
             10,
         )
-        .await;
+        .await
+        .unwrap();
 
     let message = "How are you?, explain please".to_string();
 
@@ -3,7 +3,7 @@ use std::marker::PhantomData;
 use std::str::FromStr;
 
 use async_graphql::{
-    http::GraphiQLSource, Context, EmptySubscription, Object, Schema, SimpleObject,
+    http::GraphiQLSource, Context, EmptySubscription, Object, Result, Schema, SimpleObject,
 };
 use async_graphql_warp::{GraphQLBadRequest, GraphQLResponse};
 use dotenvy::dotenv;
@@ -13,7 +13,9 @@ use myself::backend::core::AgentBackend;
 use myself::backend::openai::OpenAIBackend;
 use myself::database::memory::MemoryEngine;
 use myself::sdk::agent::{Agent, AgentBuilder};
-use myself::sdk::interaction::{Interaction as MyselfInteraction, InteractionState};
+use myself::sdk::interaction::{
+    Interaction as MyselfInteraction, InteractionState, InteractionsQuery,
+};
 
 use std::env::var;
 use uuid::Uuid;
@@ -65,23 +67,34 @@ impl<Backend> QueryRoot<Backend>
 where
     Backend: AgentBackend + Sized + Default + Clone + Send + Sync + 'static,
 {
-    async fn interactions<'a>(&self, ctx: &Context<'a>) -> Vec<Interaction>
-where {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
-        agent
-            .get_all_interactions()
-            .await
+    async fn interactions<'a>(
+        &self,
+        ctx: &Context<'a>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> Result<Vec<Interaction>> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
+        let defaults = InteractionsQuery::default();
+
+        Ok(agent
+            .get_all_interactions(InteractionsQuery {
+                limit: limit.unwrap_or(defaults.limit),
+                offset: offset.unwrap_or(defaults.offset),
+                ..defaults
+            })
+            .await?
             .iter()
             .map(Interaction::parse)
-            .collect()
+            .collect())
     }
 
-    async fn interaction<'a>(&self, ctx: &Context<'a>, id: String) -> Option<Interaction> {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
-        agent
-            .get_interaction(Uuid::from_str(id.as_str()).unwrap())
-            .await
-            .map(|i| Interaction::parse(&i))
+    async fn interaction<'a>(&self, ctx: &Context<'a>, id: String) -> Result<Option<Interaction>> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
+
+        Ok(agent
+            .get_interaction(Uuid::from_str(id.as_str())?)
+            .await?
+            .map(|i| Interaction::parse(&i)))
     }
 }
 
@@ -96,27 +109,27 @@ where
         user_name: String,
         constitution: String,
         memory_size: usize,
-    ) -> Interaction {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
+    ) -> Result<Interaction> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
         let interaction = agent
             .init_interaction(user_name, constitution, memory_size)
-            .await;
+            .await?;
 
-        Interaction::parse(&interaction)
+        Ok(Interaction::parse(&interaction))
     }
 
     async fn interact_default<'a>(
         &self,
         ctx: &Context<'a>,
         message: String,
-    ) -> InteractionResponse {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
-        let (_, response) = agent.interact_default(&message).await.unwrap();
+    ) -> Result<InteractionResponse> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
+        let (_, response) = agent.interact_default(&message).await?;
 
-        InteractionResponse {
+        Ok(InteractionResponse {
             response: response.content,
-            interaction: Interaction::parse(&agent.get_default_interaction().await),
-        }
+            interaction: Interaction::parse(&agent.get_default_interaction().await?),
+        })
     }
 
     async fn interact<'a>(
@@ -124,17 +137,22 @@ where
         ctx: &Context<'a>,
         id: String,
         message: String,
-    ) -> InteractionResponse {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
-        let uuid = Uuid::from_str(id.as_str()).unwrap();
+    ) -> Result<InteractionResponse> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
+        let uuid = Uuid::from_str(id.as_str())?;
+
+        let (_, response) = agent.interact(uuid, &message).await?;
 
-        let (_, response) = agent.interact(uuid, &message).await.unwrap();
+        let interaction = agent
+            .get_interaction(uuid)
+            .await?
+            .ok_or("interaction not found")?;
 
-        InteractionResponse {
+        Ok(InteractionResponse {
             // TODO: Improve memory management
             response: response.content,
-            interaction: Interaction::parse(&agent.get_interaction(uuid).await.unwrap()),
-        }
+            interaction: Interaction::parse(&interaction),
+        })
     }
 
     async fn update_constitution<'a>(
@@ -142,22 +160,22 @@ where
         ctx: &Context<'a>,
         id: String,
         constitution: String,
-    ) -> Interaction {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
+    ) -> Result<Interaction> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
         let interaction = agent
-            .update_long_term_memory(Uuid::from_str(id.as_str()).unwrap(), constitution)
-            .await;
+            .update_long_term_memory(Uuid::from_str(id.as_str())?, constitution)
+            .await?;
 
-        Interaction::parse(&interaction)
+        Ok(Interaction::parse(&interaction))
     }
 
-    async fn forget_memory<'a>(&self, ctx: &Context<'a>, id: String) -> Interaction {
-        let mut agent = ctx.data::<Agent<Backend>>().unwrap().to_owned();
+    async fn forget_memory<'a>(&self, ctx: &Context<'a>, id: String) -> Result<Interaction> {
+        let mut agent = ctx.data::<Agent<Backend>>()?.to_owned();
         let interaction = agent
-            .forgot_short_term_memory(Uuid::from_str(id.as_str()).unwrap())
-            .await;
+            .forgot_short_term_memory(Uuid::from_str(id.as_str())?)
+            .await?;
 
-        Interaction::parse(&interaction.unwrap())
+        Ok(Interaction::parse(&interaction))
     }
 }
 
@@ -168,13 +186,16 @@ async fn main() {
 
     dotenv().ok();
 
-    let llm_engine = OpenAIBackend::new(var("OPENAI_API_KEY").unwrap());
-    let memory_engine = MemoryEngine::new(var("DATABASE_URL").unwrap()).await;
+    let llm_engine = OpenAIBackend::new(var("OPENAI_API_KEY").unwrap()).unwrap();
+    let memory_engine = MemoryEngine::new(var("DATABASE_URL").unwrap())
+        .await
+        .unwrap();
 
     let agent = AgentBuilder::new()
         .name("AI".to_string())
         .build(llm_engine, memory_engine)
-        .await;
+        .await
+        .unwrap();
 
     let schema = Schema::build(
         QueryRoot {
@@ -0,0 +1,34 @@
+use std::env::var;
+
+use myself::{
+    backend::local::LocalBackend, database::memory::MemoryEngine, llm::LLMDialect,
+    sdk::agent::AgentBuilder,
+};
+
+#[tokio::main]
+async fn main() {
+    // Point LOCAL_LLM_URL at a running server, e.g. `ollama serve` with
+    // LOCAL_LLM_DIALECT=ollama, or a llama.cpp server at http://localhost:8080/v1.
+    let base_url = var("LOCAL_LLM_URL").unwrap_or("http://localhost:11434".to_string());
+    let model_name = var("LOCAL_LLM_MODEL").unwrap_or("llama3".to_string());
+
+    let dialect = match var("LOCAL_LLM_DIALECT").as_deref() {
+        Ok("openai") => LLMDialect::OpenAI,
+        _ => LLMDialect::Ollama,
+    };
+
+    let mut agent = AgentBuilder::new()
+        .name("LocalBot".to_string())
+        .default_constitution("A talkative chatbot conversation".to_string())
+        .build(
+            LocalBackend::new(base_url, dialect, model_name),
+            MemoryEngine::in_memory(),
+        )
+        .await
+        .unwrap();
+
+    let message = "Hello World".to_string();
+    let (_, output) = agent.interact_default(&message).await.unwrap();
+
+    println!("{}", output.content);
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use myself::{
+    backend::mock::{MockBackend, MockReply},
+    database::memory::MemoryEngine,
+    sdk::agent::AgentBuilder,
+};
+
+#[tokio::main]
+async fn main() {
+    // Runs entirely offline: no OpenAI key or database required.
+    let backend = MockBackend::new()
+        .reply("Hi! I'm a scripted reply.")
+        .rule(
+            "weather",
+            MockReply::Text("Always sunny in tests.".to_string()),
+        )
+        .responder(|turn| MockReply::Text(format!("You said {} things", turn.len())))
+        .latency(Duration::from_millis(10));
+
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    for message in ["Hello", "How is the weather?", "Bye"] {
+        let (_, output) = agent.interact_default(&message.to_string()).await.unwrap();
+        println!("{} -> {}", message, output.content);
+    }
+
+    for request in backend.requests() {
+        println!(
+            "{:?}: {}",
+            request.kind,
+            request
+                .blocks
+                .iter()
+                .map(|block| block.content.as_str())
+                .collect::<Vec<_>>()
+                .join(" | ")
+        );
+    }
+}
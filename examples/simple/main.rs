@@ -11,7 +11,8 @@ async fn main() {
         .name("ChatBot".to_string())
         .default_constitution("A talkative chatbot conversation".to_string())
         .build_default()
-        .await;
+        .await
+        .unwrap();
 
     let message = "Hello World".to_string();
     let (_, output) = agent.interact_default(&message).await.unwrap();
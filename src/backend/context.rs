@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use tiktoken_rs::{
+    get_bpe_from_tokenizer,
+    model::get_context_size,
+    tokenizer::{get_tokenizer, Tokenizer},
+    CoreBPE,
+};
+
+use crate::{
+    llm::{LLMConfiguration, LLMEngineError},
+    llm_responses::ChatMessage,
+};
+
+// Every chat message is wrapped as <|start|>{role/name}\n{content}<|end|>\n and
+// every reply is primed with <|start|>assistant<|message|>.
+const TOKENS_PER_MESSAGE: usize = 4;
+const TOKENS_PER_REPLY: usize = 3;
+
+static ENCODINGS: OnceLock<Mutex<HashMap<Tokenizer, Arc<CoreBPE>>>> = OnceLock::new();
+
+#[derive(Clone)]
+pub struct TokenCounter {
+    bpe: Arc<CoreBPE>,
+}
+
+impl TokenCounter {
+    pub fn for_model(model_name: &str) -> Result<Self, LLMEngineError> {
+        let tokenizer = get_tokenizer(model_name).unwrap_or(Tokenizer::Cl100kBase);
+
+        let mut encodings = ENCODINGS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(bpe) = encodings.get(&tokenizer) {
+            return Ok(Self { bpe: bpe.clone() });
+        }
+
+        let bpe = Arc::new(
+            get_bpe_from_tokenizer(tokenizer)
+                .map_err(|err| LLMEngineError::InvalidConfiguration(err.to_string()))?,
+        );
+
+        encodings.insert(tokenizer, bpe.clone());
+
+        Ok(Self { bpe })
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    pub fn count_message(&self, message: &ChatMessage) -> usize {
+        TOKENS_PER_MESSAGE
+            + self.count(&message.role)
+            + self.count(&message.content)
+            + message
+                .name
+                .as_deref()
+                .map(|name| self.count(name))
+                .unwrap_or(0)
+            + message
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|call| self.count(&call.function.name) + self.count(&call.function.arguments))
+                .sum::<usize>()
+    }
+
+    pub fn count_messages(&self, messages: &[ChatMessage]) -> usize {
+        TOKENS_PER_REPLY
+            + messages
+                .iter()
+                .map(|message| self.count_message(message))
+                .sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContextBudget {
+    pub context_window: usize,
+    pub reserved_for_completion: usize,
+}
+
+impl ContextBudget {
+    pub fn new(context_window: usize, reserved_for_completion: usize) -> Self {
+        Self {
+            context_window,
+            reserved_for_completion,
+        }
+    }
+
+    pub fn for_configuration(configuration: &LLMConfiguration) -> Self {
+        Self::new(
+            configuration
+                .context_window
+                .unwrap_or_else(|| get_context_size(&configuration.model_name)),
+            configuration.max_tokens,
+        )
+    }
+
+    pub fn available(&self) -> usize {
+        self.context_window
+            .saturating_sub(self.reserved_for_completion)
+    }
+
+    // Keeps every `required_head` and `required_tail` message and as many of the
+    // most recent `history` messages as fit, dropping the oldest ones first.
+    pub fn fit(
+        &self,
+        counter: &TokenCounter,
+        required_head: Vec<ChatMessage>,
+        history: Vec<ChatMessage>,
+        required_tail: Vec<ChatMessage>,
+    ) -> Result<Vec<ChatMessage>, LLMEngineError> {
+        let available = self.available();
+
+        let required = counter.count_messages(&required_head)
+            + required_tail
+                .iter()
+                .map(|message| counter.count_message(message))
+                .sum::<usize>();
+
+        if required > available {
+            return Err(LLMEngineError::ContextWindowExceeded {
+                required,
+                available,
+            });
+        }
+
+        let mut remaining = available - required;
+        let mut kept = Vec::new();
+
+        for message in history.into_iter().rev() {
+            let tokens = counter.count_message(&message);
+
+            if tokens > remaining {
+                break;
+            }
+
+            remaining -= tokens;
+            kept.push(message);
+        }
+
+        kept.reverse();
+
+        Ok(required_head
+            .into_iter()
+            .chain(kept)
+            .chain(required_tail)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn counter() -> TokenCounter {
+        TokenCounter::for_model("gpt-3.5-turbo").unwrap()
+    }
+
+    #[test]
+    fn messages_count_their_framing() {
+        let counter = counter();
+        let hello = message("user", "hello world");
+
+        assert_eq!(counter.count("hello world"), 2);
+        assert_eq!(counter.count_message(&hello), TOKENS_PER_MESSAGE + 1 + 2);
+        assert_eq!(
+            counter.count_messages(&[hello.clone(), hello]),
+            TOKENS_PER_REPLY + 2 * (TOKENS_PER_MESSAGE + 1 + 2)
+        );
+    }
+
+    #[test]
+    fn unknown_models_are_counted_with_cl100k() {
+        assert_eq!(
+            TokenCounter::for_model("llama3")
+                .unwrap()
+                .count("hello world"),
+            counter().count("hello world")
+        );
+    }
+
+    #[test]
+    fn history_is_dropped_oldest_first() {
+        let counter = counter();
+        let system = message("system", "Be brief.");
+        let input = message("user", "What now?");
+        let history = ["one", "two", "three", "four"]
+            .map(|content| message("user", content))
+            .to_vec();
+
+        let required =
+            counter.count_messages(std::slice::from_ref(&system)) + counter.count_message(&input);
+        let per_message = counter.count_message(&history[0]);
+
+        // Room for the required messages and two of the history.
+        let budget = ContextBudget::new(required + 2 * per_message + 10, 10);
+
+        let fitted = budget
+            .fit(&counter, vec![system], history, vec![input])
+            .unwrap()
+            .into_iter()
+            .map(|message| message.content)
+            .collect::<Vec<_>>();
+
+        assert_eq!(fitted, ["Be brief.", "three", "four", "What now?"]);
+    }
+
+    #[test]
+    fn required_messages_over_the_window_are_an_error() {
+        let counter = counter();
+        let system = message("system", "Be brief.");
+        let required = counter.count_messages(std::slice::from_ref(&system));
+
+        let result = ContextBudget::new(required - 1, 0).fit(
+            &counter,
+            vec![system],
+            vec![message("user", "hello")],
+            Vec::new(),
+        );
+
+        assert!(matches!(
+            result,
+            Err(LLMEngineError::ContextWindowExceeded { required: r, available })
+                if r == required && available == required - 1
+        ));
+    }
+
+    #[test]
+    fn room_is_reserved_for_the_completion() {
+        let configuration = LLMConfiguration {
+            context_window: Some(100),
+            max_tokens: 60,
+            ..Default::default()
+        };
+
+        let budget = ContextBudget::for_configuration(&configuration);
+        assert_eq!(budget.available(), 40);
+
+        let counter = counter();
+        let system = message("system", "Be brief.");
+        let history = vec![message("user", "hello"); 10];
+
+        let fitted = budget
+            .fit(&counter, vec![system.clone()], history.clone(), Vec::new())
+            .unwrap();
+        assert!(counter.count_messages(&fitted) <= 40);
+
+        let unreserved = ContextBudget::new(100, 0)
+            .fit(&counter, vec![system], history, Vec::new())
+            .unwrap();
+        assert!(fitted.len() < unreserved.len());
+
+        assert_eq!(ContextBudget::new(50, 60).available(), 0);
+    }
+
+    #[test]
+    fn the_window_defaults_to_the_model_size() {
+        let configuration = LLMConfiguration {
+            model_name: "gpt-4".to_string(),
+            max_tokens: 1000,
+            ..Default::default()
+        };
+
+        let budget = ContextBudget::for_configuration(&configuration);
+        assert_eq!(budget.context_window, 8192);
+        assert_eq!(budget.available(), 7192);
+    }
+}
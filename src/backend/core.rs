@@ -1,14 +1,90 @@
-use crate::sdk::interaction::{Interaction, InteractionBlock, WithAgent};
+use std::pin::Pin;
+
+use crate::{
+    error::MyselfError,
+    sdk::{
+        interaction::{Interaction, InteractionBlock, WithAgent},
+        usage::TokenUsage,
+    },
+};
 use async_trait::async_trait;
+use futures::{stream, Stream};
+
+pub type ResponseStream = Pin<Box<dyn Stream<Item = Result<String, MyselfError>> + Send>>;
+
+// What a backend streams: the text of the response as it is generated, and
+// the usage of the call once the server reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResponseDelta {
+    Text(String),
+    Usage(TokenUsage),
+}
+
+pub type ResponseDeltaStream =
+    Pin<Box<dyn Stream<Item = Result<ResponseDelta, MyselfError>> + Send>>;
 
 #[async_trait]
 pub trait AgentBackend
 where
-    Self: Sized + Default + Clone,
+    Self: Sized + Default + Clone + Send + Sync,
 {
     async fn predict_response(
         &mut self,
         interaction: Interaction<Self, WithAgent>,
         input: &InteractionBlock,
-    ) -> InteractionBlock;
+    ) -> Result<InteractionBlock, MyselfError>;
+
+    // Produces the next step of a turn: either the final agent block or the tool
+    // calls the agent wants to make. `turn` holds the user input followed by the
+    // tool calls and results made so far. Backends without tool support fall
+    // back to `predict_response`.
+    async fn predict_step(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        turn: &[InteractionBlock],
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        let input = turn
+            .first()
+            .ok_or_else(|| MyselfError::Configuration("empty interaction turn".to_string()))?;
+
+        Ok(vec![self.predict_response(interaction, input).await?])
+    }
+
+    // Streams the response as text deltas, followed by its usage when known.
+    // Backends without native streaming yield the whole response as a single
+    // delta.
+    async fn predict_response_stream(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<ResponseDeltaStream, MyselfError> {
+        let response = self.predict_response(interaction, input).await?;
+
+        let deltas = std::iter::once(ResponseDelta::Text(response.content))
+            .chain(response.usage.map(ResponseDelta::Usage))
+            .map(Ok)
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(stream::iter(deltas)))
+    }
+
+    // Condenses `blocks` into a new short-term memory for the interaction,
+    // folding in its current `short_term_memory`. Returns the summary along
+    // with the usage of the call.
+    async fn summarize(
+        &mut self,
+        _interaction: Interaction<Self, WithAgent>,
+        _blocks: &[InteractionBlock],
+    ) -> Result<(String, Option<TokenUsage>), MyselfError> {
+        Err(MyselfError::Configuration(
+            "backend does not support summarization".to_string(),
+        ))
+    }
+
+    // Embeds each of `texts` for semantic recall, in the same order.
+    async fn embed(&mut self, _texts: &[String]) -> Result<Vec<Vec<f32>>, MyselfError> {
+        Err(MyselfError::Configuration(
+            "backend does not support embeddings".to_string(),
+        ))
+    }
 }
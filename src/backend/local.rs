@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::MyselfError,
+    llm::{LLMDialect, LLMEngine, RetryPolicy},
+    sdk::{
+        interaction::{Interaction, InteractionBlock, WithAgent},
+        usage::TokenUsage,
+    },
+};
+
+use super::{
+    core::{AgentBackend, ResponseDeltaStream},
+    openai::{chat_response, chat_response_stream, chat_step, chat_summary},
+};
+
+// Runs agents against self-hosted models, e.g. a llama.cpp or vLLM server
+// (`LLMDialect::OpenAI`, base URL ending in `/v1`) or Ollama
+// (`LLMDialect::Ollama`, base URL like `http://localhost:11434`).
+#[derive(Default, Clone)]
+pub struct LocalBackend {
+    pub engine: LLMEngine,
+}
+
+impl LocalBackend {
+    pub fn new(
+        base_url: impl Into<String>,
+        dialect: LLMDialect,
+        model_name: impl Into<String>,
+    ) -> Self {
+        let engine = LLMEngine::local(base_url, dialect, model_name);
+        Self { engine }
+    }
+
+    pub fn openai_compatible(base_url: impl Into<String>, model_name: impl Into<String>) -> Self {
+        Self::new(base_url, LLMDialect::OpenAI, model_name)
+    }
+
+    pub fn ollama(base_url: impl Into<String>, model_name: impl Into<String>) -> Self {
+        Self::new(base_url, LLMDialect::Ollama, model_name)
+    }
+
+    pub fn with_access_token(mut self, access_token: impl Into<String>) -> Self {
+        self.engine = self.engine.with_access_token(access_token);
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.engine = self.engine.with_retry_policy(retry_policy);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.engine = self.engine.with_timeout(timeout);
+        self
+    }
+
+    pub fn with_embedding_model(mut self, embedding_model: impl Into<String>) -> Self {
+        self.engine = self.engine.with_embedding_model(embedding_model);
+        self
+    }
+}
+
+#[async_trait]
+impl AgentBackend for LocalBackend {
+    async fn predict_response(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        chat_response(&self.engine, interaction, input).await
+    }
+
+    async fn predict_step(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        turn: &[InteractionBlock],
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        chat_step(&self.engine, interaction, turn).await
+    }
+
+    async fn predict_response_stream(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<ResponseDeltaStream, MyselfError> {
+        chat_response_stream(&self.engine, interaction, input).await
+    }
+
+    async fn summarize(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        blocks: &[InteractionBlock],
+    ) -> Result<(String, Option<TokenUsage>), MyselfError> {
+        chat_summary(&self.engine, interaction, blocks).await
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>, MyselfError> {
+        Ok(self.engine.embeddings(texts.to_vec()).await?)
+    }
+}
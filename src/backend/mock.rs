@@ -0,0 +1,377 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::stream;
+
+use crate::{
+    error::MyselfError,
+    llm::LLMEngineError,
+    sdk::{
+        interaction::{Interaction, InteractionBlock, InteractionBlockRole, WithAgent},
+        usage::TokenUsage,
+    },
+};
+
+use super::core::{AgentBackend, ResponseDelta, ResponseDeltaStream};
+
+#[derive(Clone, Debug)]
+pub enum MockReply {
+    Text(String),
+    ToolCalls(Vec<MockToolCall>),
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct MockToolCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+impl MockToolCall {
+    pub fn new(name: impl Into<String>, arguments: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            arguments: arguments.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MockRequestKind {
+    Response,
+    Summary,
+}
+
+// Interactions are recorded without their agent, which would otherwise hold
+// a reference back to the backend that recorded them.
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+    pub kind: MockRequestKind,
+    pub interaction: Interaction<MockBackend>,
+    pub blocks: Vec<InteractionBlock>,
+}
+
+type Responder = Arc<dyn Fn(&[InteractionBlock]) -> MockReply + Send + Sync>;
+
+#[derive(Default)]
+struct MockState {
+    queue: Vec<MockReply>,
+    rules: Vec<(String, MockReply)>,
+    responder: Option<Responder>,
+    summary: Option<String>,
+    latency: Option<Duration>,
+    requests: Vec<MockRequest>,
+    tool_calls: usize,
+}
+
+// Replies are taken from the queue first, then from the first rule whose
+// pattern appears in the latest block of the turn, then from the responder.
+// Once tools were called the latest block is a tool result, so a rule that
+// asks for a tool call does not fire again on its own result.
+// Clones share their script and recorded requests, so the backend handed to
+// an agent can still be inspected afterwards.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reply(self, text: impl Into<String>) -> Self {
+        self.push(MockReply::Text(text.into()))
+    }
+
+    pub fn tool_calls(self, calls: Vec<MockToolCall>) -> Self {
+        self.push(MockReply::ToolCalls(calls))
+    }
+
+    pub fn fail(self, message: impl Into<String>) -> Self {
+        self.push(MockReply::Error(message.into()))
+    }
+
+    pub fn push(self, reply: MockReply) -> Self {
+        self.lock().queue.push(reply);
+        self
+    }
+
+    pub fn rule(self, pattern: impl Into<String>, reply: MockReply) -> Self {
+        self.lock().rules.push((pattern.into(), reply));
+        self
+    }
+
+    pub fn responder(
+        self,
+        responder: impl Fn(&[InteractionBlock]) -> MockReply + Send + Sync + 'static,
+    ) -> Self {
+        self.lock().responder = Some(Arc::new(responder));
+        self
+    }
+
+    pub fn summary(self, summary: impl Into<String>) -> Self {
+        self.lock().summary = Some(summary.into());
+        self
+    }
+
+    pub fn latency(self, latency: Duration) -> Self {
+        self.lock().latency = Some(latency);
+        self
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.lock().requests.clone()
+    }
+
+    pub fn clear_requests(&self) {
+        self.lock().requests.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    async fn wait(&self) {
+        let latency = self.lock().latency;
+
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
+    fn record(
+        &self,
+        kind: MockRequestKind,
+        interaction: &Interaction<Self, WithAgent>,
+        blocks: &[InteractionBlock],
+    ) {
+        self.lock().requests.push(MockRequest {
+            kind,
+            interaction: interaction.without_agent(),
+            blocks: blocks.to_vec(),
+        });
+    }
+
+    fn next_reply(&self, turn: &[InteractionBlock]) -> Result<MockReply, MyselfError> {
+        let mut state = self.lock();
+
+        if !state.queue.is_empty() {
+            return Ok(state.queue.remove(0));
+        }
+
+        let message = turn
+            .last()
+            .map(|block| block.content.as_str())
+            .unwrap_or_default();
+
+        if let Some((_, reply)) = state
+            .rules
+            .iter()
+            .find(|(pattern, _)| message.contains(pattern.as_str()))
+        {
+            return Ok(reply.clone());
+        }
+
+        if let Some(responder) = state.responder.clone() {
+            drop(state);
+            return Ok(responder(turn));
+        }
+
+        Err(
+            LLMEngineError::InvalidResponse("mock backend has no scripted reply".to_string())
+                .into(),
+        )
+    }
+}
+
+const MOCK_EMBEDDING_DIMENSIONS: usize = 64;
+
+// Hashes each word into a bucket, so texts that share words are similar.
+fn mock_embedding(text: &str) -> Vec<f32> {
+    let mut embedding = vec![0.0; MOCK_EMBEDDING_DIMENSIONS];
+
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        embedding[(hash % MOCK_EMBEDDING_DIMENSIONS as u64) as usize] += 1.0;
+    }
+
+    embedding
+}
+
+// Counts words as tokens so usage reports have something to aggregate.
+fn mock_usage(turn: &[InteractionBlock], reply: &[InteractionBlock]) -> TokenUsage {
+    let words = |blocks: &[InteractionBlock]| {
+        blocks
+            .iter()
+            .map(|block| block.content.split_whitespace().count())
+            .sum()
+    };
+
+    TokenUsage {
+        model_name: "mock".to_string(),
+        prompt_tokens: words(turn),
+        completion_tokens: words(reply),
+        finish_reason: Some(
+            if reply.iter().any(|block| block.tool_call_id.is_some()) {
+                "tool_calls"
+            } else {
+                "stop"
+            }
+            .to_string(),
+        ),
+    }
+}
+
+impl fmt::Debug for MockBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+
+        f.debug_struct("MockBackend")
+            .field("queue", &state.queue)
+            .field("rules", &state.rules)
+            .field("requests", &state.requests.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl AgentBackend for MockBackend {
+    async fn predict_response(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        let mut blocks = self
+            .predict_step(interaction, std::slice::from_ref(input))
+            .await?;
+
+        match blocks.pop() {
+            Some(block) if matches!(block.role, InteractionBlockRole::Agent) => Ok(block),
+            _ => Err(LLMEngineError::InvalidResponse(
+                "mock reply requested tool calls outside of a tool loop".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    async fn predict_step(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        turn: &[InteractionBlock],
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        self.record(MockRequestKind::Response, &interaction, turn);
+        self.wait().await;
+
+        let mut blocks = match self.next_reply(turn)? {
+            MockReply::Text(text) => vec![InteractionBlock::new_agent(
+                interaction.id,
+                text,
+                Some(interaction.agent()?.my_name.clone()),
+            )],
+            MockReply::ToolCalls(calls) => {
+                let mut state = self.lock();
+
+                calls
+                    .into_iter()
+                    .map(|call| {
+                        state.tool_calls += 1;
+
+                        InteractionBlock::new_tool_call(
+                            interaction.id,
+                            call.name,
+                            call.arguments,
+                            format!("call_{}", state.tool_calls),
+                        )
+                    })
+                    .collect()
+            }
+            MockReply::Error(message) => {
+                return Err(LLMEngineError::InvalidResponse(message).into())
+            }
+        };
+
+        let usage = mock_usage(turn, &blocks);
+
+        if let Some(block) = blocks.first_mut() {
+            block.usage = Some(usage);
+        }
+
+        Ok(blocks)
+    }
+
+    // Streams the reply word by word so consumers see more than one delta.
+    async fn predict_response_stream(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<ResponseDeltaStream, MyselfError> {
+        let response = self.predict_response(interaction, input).await?;
+
+        let deltas = response
+            .content
+            .split_inclusive(' ')
+            .map(|delta| ResponseDelta::Text(delta.to_string()))
+            .chain(response.usage.map(ResponseDelta::Usage))
+            .map(Ok)
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(stream::iter(deltas)))
+    }
+
+    async fn summarize(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        blocks: &[InteractionBlock],
+    ) -> Result<(String, Option<TokenUsage>), MyselfError> {
+        self.record(MockRequestKind::Summary, &interaction, blocks);
+        self.wait().await;
+
+        let configured = self.lock().summary.clone();
+
+        let summary = match configured {
+            Some(summary) => summary,
+            None => {
+                let transcript = interaction.prompt_template()?.transcript(blocks)?;
+
+                [interaction.short_term_memory.trim(), &transcript]
+                    .into_iter()
+                    .filter(|part| !part.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+
+        let usage = mock_usage(
+            blocks,
+            &[InteractionBlock::new_system(
+                interaction.id,
+                summary.clone(),
+                None,
+            )],
+        );
+
+        Ok((summary, Some(usage)))
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>, MyselfError> {
+        self.wait().await;
+
+        Ok(texts.iter().map(|text| mock_embedding(text)).collect())
+    }
+}
@@ -1,2 +1,5 @@
+pub mod context;
 pub mod core;
+pub mod local;
+pub mod mock;
 pub mod openai;
@@ -1,11 +1,28 @@
+use std::time::Duration;
+
+use async_stream::try_stream;
 use async_trait::async_trait;
+use futures::StreamExt;
 
 use crate::{
-    llm::LLMEngine,
-    sdk::interaction::{Interaction, InteractionBlock, WithAgent},
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMEngine, LLMEngineError, RetryPolicy},
+    llm_responses::{
+        ChatCompletionResponse, ChatFunction, ChatFunctionCall, ChatMessage, ChatTool,
+        ChatToolCall, Usage,
+    },
+    sdk::{
+        interaction::{Interaction, InteractionBlock, InteractionBlockRole, WithAgent},
+        template::{PromptContext, PromptTemplate},
+        tool::Toolbox,
+        usage::TokenUsage,
+    },
 };
 
-use super::core::AgentBackend;
+use super::{
+    context::{ContextBudget, TokenCounter},
+    core::{AgentBackend, ResponseDelta, ResponseDeltaStream},
+};
 
 #[derive(Default, Clone)]
 pub struct OpenAIBackend {
@@ -13,10 +30,535 @@ pub struct OpenAIBackend {
 }
 
 impl OpenAIBackend {
-    pub fn new(api_key: String) -> Self {
-        let engine = LLMEngine::new(api_key);
-        Self { engine }
+    // Like `new_defaults`, with the given API key.
+    pub fn new(api_key: String) -> Result<Self, MyselfError> {
+        let engine = LLMEngine::new(api_key)?;
+        Ok(Self { engine })
+    }
+
+    pub fn with_model(api_key: String, model_name: impl Into<String>) -> Result<Self, MyselfError> {
+        let engine = LLMEngine::new(api_key)?.with_model(model_name);
+        Ok(Self { engine })
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.engine = self.engine.with_retry_policy(retry_policy);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.engine = self.engine.with_timeout(timeout);
+        self
+    }
+
+    pub fn with_embedding_model(mut self, embedding_model: impl Into<String>) -> Self {
+        self.engine = self.engine.with_embedding_model(embedding_model);
+        self
+    }
+
+    // Reads the API key and configuration from the `OPENAI_*` variables.
+    pub fn new_defaults() -> Result<Self, MyselfError> {
+        let engine = LLMEngine::new_defaults()?;
+        Ok(Self { engine })
+    }
+}
+
+pub(crate) fn chat_role(role: &InteractionBlockRole) -> &'static str {
+    match role {
+        InteractionBlockRole::System => "system",
+        InteractionBlockRole::User => "user",
+        InteractionBlockRole::Agent => "assistant",
+        InteractionBlockRole::ToolCall => "assistant",
+        InteractionBlockRole::Tool => "tool",
+    }
+}
+
+// OpenAI only accepts names matching ^[a-zA-Z0-9_-]{1,64}$, so speaker names
+// like "Joe (Human)" need to be normalized before they are sent.
+pub(crate) fn chat_name(name: &str) -> Option<String> {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect::<String>();
+
+    let trimmed = name.trim_matches('_');
+
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+pub(crate) fn chat_message(block: &InteractionBlock) -> ChatMessage {
+    match block.role {
+        InteractionBlockRole::ToolCall => ChatMessage {
+            role: chat_role(&block.role).to_string(),
+            tool_calls: Some(vec![ChatToolCall {
+                id: block.tool_call_id.clone().unwrap_or_default(),
+                kind: "function".to_string(),
+                function: ChatFunctionCall {
+                    name: block.name.clone().unwrap_or_default(),
+                    arguments: block.content.clone(),
+                },
+            }]),
+            ..Default::default()
+        },
+        InteractionBlockRole::Tool => ChatMessage {
+            role: chat_role(&block.role).to_string(),
+            content: block.content.clone(),
+            tool_call_id: block.tool_call_id.clone(),
+            ..Default::default()
+        },
+        _ => ChatMessage {
+            role: chat_role(&block.role).to_string(),
+            content: block.content.clone(),
+            name: block.name.as_deref().and_then(chat_name),
+            ..Default::default()
+        },
+    }
+}
+
+// Tool calls requested together are stored as consecutive blocks, but must be
+// sent back as a single assistant message followed by their results.
+pub(crate) fn chat_messages(blocks: &[InteractionBlock]) -> Vec<ChatMessage> {
+    let mut messages: Vec<ChatMessage> = Vec::new();
+
+    for block in blocks {
+        let message = chat_message(block);
+
+        match (messages.last_mut(), &message.tool_calls) {
+            (Some(last), Some(tool_calls)) if last.tool_calls.is_some() => last
+                .tool_calls
+                .get_or_insert_with(Vec::new)
+                .extend(tool_calls.iter().cloned()),
+            _ => messages.push(message),
+        }
+    }
+
+    messages
+}
+
+// A tool result is only valid right after the assistant message that requested
+// it, so results whose call was cut off by the memory window are dropped.
+pub(crate) fn drop_orphan_tool_results(messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+    let mut requested = Vec::new();
+
+    messages
+        .into_iter()
+        .filter(|message| {
+            if let Some(tool_calls) = &message.tool_calls {
+                requested.extend(tool_calls.iter().map(|call| call.id.clone()));
+            }
+
+            match &message.tool_call_id {
+                Some(id) if message.role == "tool" => requested.contains(id),
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+// Whether anyone but `agent_name` and a single user speaks in `blocks`.
+fn several_speakers<'a>(
+    agent_name: &str,
+    blocks: impl Iterator<Item = &'a InteractionBlock>,
+) -> bool {
+    let mut user_name = None;
+
+    for block in blocks {
+        match block.role {
+            InteractionBlockRole::Agent if block.name.as_deref() != Some(agent_name) => {
+                return true;
+            }
+            InteractionBlockRole::User => match user_name {
+                Some(name) if name != &block.name => return true,
+                _ => user_name = Some(&block.name),
+            },
+            _ => {}
+        }
+    }
+
+    false
+}
+
+// Messages of other agents would otherwise be sent as the answering agent's
+// own, so in conversations with several speakers every message not written by
+// `agent_name` is sent as a user message laid out by the template's `message`
+// part, which names its speaker.
+fn label_speakers(
+    template: &PromptTemplate,
+    agent_name: &str,
+    blocks: &[InteractionBlock],
+) -> Result<Vec<InteractionBlock>, MyselfError> {
+    blocks
+        .iter()
+        .map(|block| match block.role {
+            InteractionBlockRole::User | InteractionBlockRole::Agent
+                if block.name.as_deref() != Some(agent_name) =>
+            {
+                Ok(InteractionBlock {
+                    role: InteractionBlockRole::User,
+                    content: template.transcript(std::slice::from_ref(block))?,
+                    ..block.clone()
+                })
+            }
+            _ => Ok(block.clone()),
+        })
+        .collect()
+}
+
+pub(crate) fn chat_tools(tools: &Toolbox) -> Vec<ChatTool> {
+    tools
+        .tools()
+        .iter()
+        .map(|tool| ChatTool {
+            kind: "function".to_string(),
+            function: ChatFunction {
+                name: tool.name(),
+                description: tool.description(),
+                parameters: tool.parameters(),
+            },
+        })
+        .collect()
+}
+
+// Returns the first choice's message along with the usage of the call.
+fn first_choice(
+    response: ChatCompletionResponse,
+    configuration: &LLMConfiguration,
+) -> Result<(ChatMessage, Option<TokenUsage>), LLMEngineError> {
+    let ChatCompletionResponse {
+        model,
+        choices,
+        usage,
+        ..
+    } = response;
+
+    let choice = choices.into_iter().next().ok_or_else(|| {
+        LLMEngineError::InvalidResponse("missing chat completion choice".to_string())
+    })?;
+
+    let usage = token_usage(model, usage, choice.finish_reason, configuration);
+
+    Ok((choice.message, usage))
+}
+
+// Servers that do not report usage leave it zeroed.
+fn token_usage(
+    model: String,
+    usage: Usage,
+    finish_reason: Option<String>,
+    configuration: &LLMConfiguration,
+) -> Option<TokenUsage> {
+    (usage.prompt_tokens > 0 || usage.completion_tokens > 0).then(|| TokenUsage {
+        model_name: if model.is_empty() {
+            configuration.model_name.clone()
+        } else {
+            model
+        },
+        prompt_tokens: usage.prompt_tokens as usize,
+        completion_tokens: usage.completion_tokens as usize,
+        finish_reason,
+    })
+}
+
+const SUMMARIZATION_PROMPT: &str = "You maintain the running summary of a conversation. \
+Merge the existing summary with the new messages into a single concise summary that keeps \
+names, facts, decisions and open questions. Reply with the summary only.";
+
+// The engine with the interaction's effective configuration.
+fn interaction_engine<Backend>(
+    engine: &LLMEngine,
+    interaction: &Interaction<Backend, WithAgent>,
+) -> Result<LLMEngine, MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let configuration = interaction.llm_configuration(engine.configuration())?;
+
+    Ok(engine.clone().with_configuration(configuration))
+}
+
+async fn prompt_messages<Backend>(
+    engine: &LLMEngine,
+    interaction: &Interaction<Backend, WithAgent>,
+    turn: &[InteractionBlock],
+) -> Result<Vec<ChatMessage>, MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let configuration = engine.configuration();
+
+    let input = turn
+        .first()
+        .ok_or_else(|| MyselfError::Configuration("empty interaction turn".to_string()))?;
+
+    let recall = interaction.recall(input, configuration).await?;
+
+    let template = interaction.prompt_template()?;
+
+    let agent_name = &interaction.agent()?.my_name;
+
+    // An agent responding to a stored block finds it in its history as well.
+    let history = recall
+        .history
+        .iter()
+        .filter(|block| turn.iter().all(|input| input.id != block.id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let system = |history: &[InteractionBlock]| -> Result<Vec<ChatMessage>, MyselfError> {
+        Ok(template
+            .render(&PromptContext {
+                agent_name,
+                user_name: &interaction.user_name,
+                constitution: &interaction.constitution,
+                summary: recall.summary.as_deref(),
+                history,
+                relevant: &recall.relevant,
+                input: &input.content,
+            })?
+            .into_iter()
+            .map(|content| ChatMessage {
+                role: "system".to_string(),
+                content,
+                ..Default::default()
+            })
+            .collect())
+    };
+
+    let (labelled, turn) = if several_speakers(agent_name, history.iter().chain(turn)) {
+        (
+            label_speakers(template, agent_name, &history)?,
+            label_speakers(template, agent_name, turn)?,
+        )
+    } else {
+        (history.clone(), turn.to_vec())
+    };
+
+    let budget = ContextBudget::for_configuration(configuration);
+    let counter = TokenCounter::for_model(&configuration.model_name)?;
+    let turn = chat_messages(&turn);
+
+    // Templates that render the history themselves get no history messages,
+    // so their transcript loses its oldest lines until the prompt fits.
+    if template.uses("history") {
+        let required = counter.count_messages(&system(&[])?)
+            + turn
+                .iter()
+                .map(|message| counter.count_message(message))
+                .sum::<usize>();
+
+        let mut remaining = budget.available().saturating_sub(required);
+        let mut first = history.len();
+
+        for block in history.iter().rev() {
+            // Each line also takes the newline that joins it to the next one.
+            let line = template.transcript(std::slice::from_ref(block))?;
+            let tokens = counter.count(&format!("{}\n", line));
+
+            if tokens > remaining {
+                break;
+            }
+
+            remaining -= tokens;
+            first -= 1;
+        }
+
+        // Lines counted apart only estimate the joined transcript, so one that
+        // still does not fit once rendered costs the next oldest line as well.
+        for skipped in first..=history.len() {
+            match budget.fit(
+                &counter,
+                system(&history[skipped..])?,
+                Vec::new(),
+                turn.clone(),
+            ) {
+                Err(LLMEngineError::ContextWindowExceeded { .. }) if skipped < history.len() => {}
+                result => return Ok(drop_orphan_tool_results(result?)),
+            }
+        }
+    }
+
+    let messages = budget.fit(&counter, system(&history)?, chat_messages(&labelled), turn)?;
+
+    Ok(drop_orphan_tool_results(messages))
+}
+
+// The functions below implement `AgentBackend` on top of any chat completions
+// engine, so backends that only differ in where they send requests share them.
+
+pub(crate) async fn chat_response<Backend>(
+    engine: &LLMEngine,
+    interaction: Interaction<Backend, WithAgent>,
+    input: &InteractionBlock,
+) -> Result<InteractionBlock, MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let engine = &interaction_engine(engine, &interaction)?;
+
+    let messages = prompt_messages(engine, &interaction, std::slice::from_ref(input)).await?;
+
+    let response = engine
+        .chat_completions_call(messages, None, Vec::new())
+        .await?;
+
+    let (message, usage) = first_choice(response, engine.configuration())?;
+
+    Ok(InteractionBlock {
+        usage,
+        ..InteractionBlock::new_agent(
+            interaction.id,
+            message.content.trim().to_string(),
+            Some(interaction.agent()?.my_name.clone()),
+        )
+    })
+}
+
+pub(crate) async fn chat_step<Backend>(
+    engine: &LLMEngine,
+    interaction: Interaction<Backend, WithAgent>,
+    turn: &[InteractionBlock],
+) -> Result<Vec<InteractionBlock>, MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let engine = &interaction_engine(engine, &interaction)?;
+
+    let agent = interaction.agent()?;
+
+    let messages = prompt_messages(engine, &interaction, turn).await?;
+
+    let response = engine
+        .chat_completions_call(messages, None, chat_tools(&agent.tools))
+        .await?;
+
+    let (message, usage) = first_choice(response, engine.configuration())?;
+
+    let mut blocks = match message.tool_calls {
+        Some(tool_calls) if !tool_calls.is_empty() => tool_calls
+            .into_iter()
+            .map(|call| {
+                InteractionBlock::new_tool_call(
+                    interaction.id,
+                    call.function.name,
+                    call.function.arguments,
+                    call.id,
+                )
+            })
+            .collect(),
+        _ => vec![InteractionBlock::new_agent(
+            interaction.id,
+            message.content.trim().to_string(),
+            Some(agent.my_name.clone()),
+        )],
+    };
+
+    if let Some(block) = blocks.first_mut() {
+        block.usage = usage;
+    }
+
+    Ok(blocks)
+}
+
+pub(crate) async fn chat_response_stream<Backend>(
+    engine: &LLMEngine,
+    interaction: Interaction<Backend, WithAgent>,
+    input: &InteractionBlock,
+) -> Result<ResponseDeltaStream, MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let engine = interaction_engine(engine, &interaction)?;
+
+    let messages = prompt_messages(&engine, &interaction, std::slice::from_ref(input)).await?;
+
+    let mut chunks = Box::pin(engine.chat_completions_stream(messages, None).await?);
+
+    Ok(Box::pin(try_stream! {
+        // The finish reason comes with the last content chunk, before the
+        // usage.
+        let mut finish_reason = None;
+
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(MyselfError::from)?;
+
+            if let Some(choice) = chunk.choices.into_iter().next() {
+                finish_reason = choice.finish_reason.or(finish_reason);
+
+                if let Some(content) = choice.delta.content.filter(|content| !content.is_empty()) {
+                    yield ResponseDelta::Text(content);
+                }
+            }
+
+            if let Some(usage) = chunk.usage.and_then(|usage| {
+                token_usage(chunk.model, usage, finish_reason.clone(), engine.configuration())
+            }) {
+                yield ResponseDelta::Usage(usage);
+            }
+        }
+    }))
+}
+
+pub(crate) async fn chat_summary<Backend>(
+    engine: &LLMEngine,
+    interaction: Interaction<Backend, WithAgent>,
+    blocks: &[InteractionBlock],
+) -> Result<(String, Option<TokenUsage>), MyselfError>
+where
+    Backend: AgentBackend,
+{
+    let engine = &interaction_engine(engine, &interaction)?;
+
+    let mut messages = vec![ChatMessage {
+        role: "system".to_string(),
+        content: SUMMARIZATION_PROMPT.to_string(),
+        ..Default::default()
+    }];
+
+    if !interaction.short_term_memory.trim().is_empty() {
+        messages.push(ChatMessage {
+            role: "system".to_string(),
+            content: format!(
+                "Existing summary:\n{}",
+                interaction.short_term_memory.trim()
+            ),
+            ..Default::default()
+        });
     }
+
+    let configuration = engine.configuration();
+
+    let messages = ContextBudget::for_configuration(configuration).fit(
+        &TokenCounter::for_model(&configuration.model_name)?,
+        messages,
+        Vec::new(),
+        vec![ChatMessage {
+            role: "user".to_string(),
+            content: format!(
+                "New messages:\n{}",
+                interaction.prompt_template()?.transcript(blocks)?
+            ),
+            ..Default::default()
+        }],
+    )?;
+
+    let response = engine
+        .chat_completions_call(messages, None, Vec::new())
+        .await?;
+
+    let (message, usage) = first_choice(response, engine.configuration())?;
+
+    Ok((message.content.trim().to_string(), usage))
 }
 
 #[async_trait]
@@ -25,43 +567,35 @@ impl AgentBackend for OpenAIBackend {
         &mut self,
         interaction: Interaction<Self, WithAgent>,
         input: &InteractionBlock,
-    ) -> InteractionBlock {
-        let compiled_interaction_blocks = interaction
-            .long_term_memory(interaction.long_term_memory_size)
-            .await
-            .iter()
-            .map(|b| {
-                format!(
-                    "{}: {}",
-                    b.name.clone().unwrap_or(b.role.to_string()),
-                    b.content
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n");
-
-        let role_name = input.role.to_string();
-
-        let prompt = format!(
-            "{}\n{}\n{}: {}\n{}: ",
-            compiled_interaction_blocks,
-            interaction.short_term_memory.clone(),
-            interaction.user_name,
-            input.content,
-            input.name.to_owned().unwrap_or(role_name),
-        );
+    ) -> Result<InteractionBlock, MyselfError> {
+        chat_response(&self.engine, interaction, input).await
+    }
 
-        println!("Prompt:\n=======\n{}\n=======", prompt);
+    async fn predict_step(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        turn: &[InteractionBlock],
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        chat_step(&self.engine, interaction, turn).await
+    }
 
-        let response = self.engine.completions_call(prompt, None).await.unwrap();
+    async fn predict_response_stream(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        input: &InteractionBlock,
+    ) -> Result<ResponseDeltaStream, MyselfError> {
+        chat_response_stream(&self.engine, interaction, input).await
+    }
 
-        let model_response = response.choices[0].text.trim().to_string();
+    async fn summarize(
+        &mut self,
+        interaction: Interaction<Self, WithAgent>,
+        blocks: &[InteractionBlock],
+    ) -> Result<(String, Option<TokenUsage>), MyselfError> {
+        chat_summary(&self.engine, interaction, blocks).await
+    }
 
-        InteractionBlock::new(
-            input.role.clone(),
-            model_response,
-            interaction.id,
-            Some(interaction.agent.unwrap().my_name),
-        )
+    async fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>, MyselfError> {
+        Ok(self.engine.embeddings(texts.to_vec()).await?)
     }
 }
@@ -1,10 +1,27 @@
 use sqlx::postgres::{PgPool, PgPoolOptions};
 
-pub async fn new_postgres_pool(database_url: String) -> PgPool {
+pub async fn new_postgres_pool(database_url: String) -> Result<PgPool, sqlx::Error> {
     // let database_url = std::env::var("DATABASE_URL").unwrap_or("sqlite://sqlite.db".to_string());
     PgPoolOptions::new()
         .max_connections(1)
         .connect(&database_url)
         .await
-        .unwrap()
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn new_sqlite_pool(
+    database_url: String,
+) -> Result<sqlx::sqlite::SqlitePool, sqlx::Error> {
+    use std::str::FromStr;
+
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    let options = SqliteConnectOptions::from_str(&database_url)?
+        .create_if_missing(true)
+        .foreign_keys(true);
+
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
 }
@@ -0,0 +1,1094 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound::{Excluded, Included, Unbounded},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    error::MyselfError,
+    llm::LLMOverrides,
+    sdk::{
+        group::{InteractionMember, Member},
+        interaction::{
+            HistoryCursor, InteractionBlock, InteractionsOrder, InteractionsQuery, Meta,
+        },
+        participant::Participant,
+        quota::Quota,
+        template::PromptTemplate,
+        usage::{ModelUsage, TokenUsage},
+    },
+};
+
+use super::store::{most_similar, AgentRecord, InteractionRecord, MemoryStore};
+
+fn model_usage<'a>(usages: impl Iterator<Item = &'a TokenUsage>) -> Vec<ModelUsage> {
+    let mut models = BTreeMap::<String, ModelUsage>::new();
+
+    for usage in usages {
+        let model = models
+            .entry(usage.model_name.clone())
+            .or_insert_with(|| ModelUsage {
+                model_name: usage.model_name.clone(),
+                ..Default::default()
+            });
+
+        model.responses += 1;
+        model.prompt_tokens += usage.prompt_tokens;
+        model.completion_tokens += usage.completion_tokens;
+    }
+
+    models.into_values().collect()
+}
+
+fn blocks_since(
+    blocks: &BTreeMap<(DateTime<Utc>, Uuid), InteractionBlock>,
+    since: Option<DateTime<Utc>>,
+) -> impl Iterator<Item = &InteractionBlock> {
+    match since {
+        Some(since) => blocks.range((Included((since, Uuid::nil())), Unbounded)),
+        None => blocks.range(..),
+    }
+    .map(|(_, block)| block)
+}
+
+fn page_of_interactions(
+    mut interactions: Vec<InteractionRecord>,
+    options: &InteractionsQuery,
+) -> Vec<InteractionRecord> {
+    interactions.sort_by(|a, b| {
+        let ordering = match options.order {
+            InteractionsOrder::CreatedAtAsc => a.created_at.cmp(&b.created_at),
+            InteractionsOrder::CreatedAtDesc => b.created_at.cmp(&a.created_at),
+            InteractionsOrder::UpdatedAtAsc => a.updated_at.cmp(&b.updated_at),
+            InteractionsOrder::UpdatedAtDesc => b.updated_at.cmp(&a.updated_at),
+        };
+
+        ordering.then(a.id.cmp(&b.id))
+    });
+
+    interactions
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .collect()
+}
+
+// The usage of a call that summarized an interaction.
+#[derive(Debug)]
+struct SummaryUsage {
+    created_at: DateTime<Utc>,
+    interaction_id: Uuid,
+    agent_id: Option<Uuid>,
+    usage: TokenUsage,
+}
+
+#[derive(Debug, Default)]
+struct InMemoryState {
+    agents: HashMap<Uuid, AgentRecord>,
+    interactions: HashMap<Uuid, InteractionRecord>,
+    blocks: HashMap<Uuid, BTreeMap<(DateTime<Utc>, Uuid), InteractionBlock>>,
+    meta: HashMap<Uuid, Meta>,
+    participants: HashMap<Uuid, Participant>,
+    members: HashMap<Uuid, Vec<InteractionMember>>,
+    summary_usage: Vec<SummaryUsage>,
+}
+
+impl InMemoryState {
+    fn check_agent(&self, agent_id: Option<Uuid>) -> Result<(), MyselfError> {
+        match agent_id {
+            Some(id) if !self.agents.contains_key(&id) => {
+                Err(MyselfError::NotFound(format!("agent {}", id)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_participant(&self, participant_id: Option<Uuid>) -> Result<(), MyselfError> {
+        match participant_id {
+            Some(id) if !self.participants.contains_key(&id) => {
+                Err(MyselfError::NotFound(format!("participant {}", id)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn summary_usage_since(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> impl Iterator<Item = &SummaryUsage> {
+        self.summary_usage
+            .iter()
+            .filter(move |summary| since.is_none_or(|since| summary.created_at >= since))
+    }
+
+    fn check_external_id(&self, participant: &Participant) -> Result<(), MyselfError> {
+        let taken = participant.external_id.is_some()
+            && self.participants.values().any(|other| {
+                other.id != participant.id && other.external_id == participant.external_id
+            });
+
+        if taken {
+            return Err(MyselfError::Configuration(format!(
+                "external_id {:?} is already used by another participant",
+                participant.external_id.as_deref().unwrap_or_default()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStore {
+    state: Arc<RwLock<InMemoryState>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, InMemoryState>, MyselfError> {
+        self.state
+            .read()
+            .map_err(|_| MyselfError::Configuration("in-memory store is poisoned".to_string()))
+    }
+
+    fn write(&self) -> Result<RwLockWriteGuard<'_, InMemoryState>, MyselfError> {
+        self.state
+            .write()
+            .map_err(|_| MyselfError::Configuration("in-memory store is poisoned".to_string()))
+    }
+}
+
+#[async_trait]
+impl MemoryStore for InMemoryStore {
+    async fn schema_version(&self) -> Result<Option<i64>, MyselfError> {
+        Ok(None)
+    }
+
+    async fn insert_agent(&self, agent: AgentRecord) -> Result<AgentRecord, MyselfError> {
+        self.write()?.agents.insert(agent.id, agent.clone());
+
+        Ok(agent)
+    }
+
+    async fn get_agent(&self, id: Uuid) -> Result<Option<AgentRecord>, MyselfError> {
+        Ok(self.read()?.agents.get(&id).cloned())
+    }
+
+    async fn get_agent_by_name(&self, name: &str) -> Result<Option<AgentRecord>, MyselfError> {
+        Ok(self
+            .read()?
+            .agents
+            .values()
+            .filter(|agent| agent.name == name)
+            .min_by_key(|agent| (agent.created_at, agent.id))
+            .cloned())
+    }
+
+    async fn list_agents(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<AgentRecord>, MyselfError> {
+        let mut agents = self.read()?.agents.values().cloned().collect::<Vec<_>>();
+
+        agents.sort_by_key(|agent| (agent.created_at, agent.id));
+
+        Ok(agents.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn update_agent(&self, agent: AgentRecord) -> Result<Option<AgentRecord>, MyselfError> {
+        Ok(self.write()?.agents.get_mut(&agent.id).map(|stored| {
+            *stored = AgentRecord {
+                created_at: stored.created_at,
+                ..agent
+            };
+            stored.clone()
+        }))
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, MyselfError> {
+        let mut state = self.write()?;
+
+        if state.agents.remove(&id).is_none() {
+            return Ok(false);
+        }
+
+        let interaction_ids = state
+            .interactions
+            .values()
+            .filter(|interaction| interaction.agent_id == Some(id))
+            .map(|interaction| interaction.id)
+            .collect::<Vec<_>>();
+
+        for interaction_id in &interaction_ids {
+            state.interactions.remove(interaction_id);
+            state.blocks.remove(interaction_id);
+            state.members.remove(interaction_id);
+        }
+
+        for members in state.members.values_mut() {
+            members.retain(|member| member.member != Member::Agent(id));
+        }
+
+        state
+            .summary_usage
+            .retain(|summary| !interaction_ids.contains(&summary.interaction_id));
+
+        // Keeps what the agent wrote in other agents' group interactions.
+        for summary in &mut state.summary_usage {
+            if summary.agent_id == Some(id) {
+                summary.agent_id = None;
+            }
+        }
+
+        for block in state
+            .blocks
+            .values_mut()
+            .flat_map(|blocks| blocks.values_mut())
+        {
+            if block.agent_id == Some(id) {
+                block.agent_id = None;
+            }
+        }
+
+        state.meta.remove(&id);
+
+        Ok(true)
+    }
+
+    async fn insert_interaction(
+        &self,
+        interaction: InteractionRecord,
+    ) -> Result<InteractionRecord, MyselfError> {
+        let mut state = self.write()?;
+
+        state.check_agent(interaction.agent_id)?;
+        state.check_participant(interaction.participant_id)?;
+
+        state
+            .interactions
+            .insert(interaction.id, interaction.clone());
+
+        Ok(interaction)
+    }
+
+    async fn get_interaction(&self, id: Uuid) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.read()?.interactions.get(&id).cloned())
+    }
+
+    async fn get_agent_interactions(
+        &self,
+        agent_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        let interactions = self
+            .read()?
+            .interactions
+            .values()
+            .filter(|interaction| interaction.agent_id == Some(agent_id))
+            .cloned()
+            .collect();
+
+        Ok(page_of_interactions(interactions, options))
+    }
+
+    async fn update_constitution(
+        &self,
+        id: Uuid,
+        constitution: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.constitution = constitution;
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn set_short_term_memory(
+        &self,
+        id: Uuid,
+        memory: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.short_term_memory = memory;
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn set_llm_overrides(
+        &self,
+        id: Uuid,
+        overrides: LLMOverrides,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.llm_overrides = overrides;
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn set_quota(
+        &self,
+        id: Uuid,
+        quota: Quota,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.quota = quota;
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn set_prompt_template(
+        &self,
+        id: Uuid,
+        template: Option<PromptTemplate>,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.prompt_template = template;
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn set_summary(
+        &self,
+        id: Uuid,
+        summary: String,
+        summarized_until: HistoryCursor,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        Ok(self.write()?.interactions.get_mut(&id).map(|interaction| {
+            interaction.short_term_memory = summary;
+            interaction.summarized_until = Some(summarized_until);
+            interaction.updated_at = updated_at;
+            interaction.clone()
+        }))
+    }
+
+    async fn append_block(
+        &self,
+        interaction_id: Uuid,
+        block: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        let mut state = self.write()?;
+
+        state.check_agent(block.agent_id)?;
+        state.check_participant(block.participant_id)?;
+
+        let interaction = state
+            .interactions
+            .get_mut(&interaction_id)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        interaction.updated_at = block.created_at;
+
+        let block = InteractionBlock {
+            interaction_id,
+            ..block.clone()
+        };
+
+        state
+            .blocks
+            .entry(interaction_id)
+            .or_default()
+            .insert((block.created_at, block.id), block.clone());
+
+        Ok(block)
+    }
+
+    async fn get_long_term_memory(
+        &self,
+        interaction_id: Uuid,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        let state = self.read()?;
+
+        let Some(blocks) = state.blocks.get(&interaction_id) else {
+            return Ok(Vec::new());
+        };
+
+        let mut recent = match before {
+            Some(cursor) => blocks
+                .range(..(cursor.created_at, cursor.id))
+                .rev()
+                .take(limit)
+                .map(|(_, block)| block.clone())
+                .collect::<Vec<_>>(),
+            None => blocks.values().rev().take(limit).cloned().collect(),
+        };
+
+        recent.reverse();
+
+        Ok(recent)
+    }
+
+    async fn get_blocks_after(
+        &self,
+        interaction_id: Uuid,
+        after: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        let state = self.read()?;
+
+        let Some(blocks) = state.blocks.get(&interaction_id) else {
+            return Ok(Vec::new());
+        };
+
+        Ok(match after {
+            Some(cursor) => blocks
+                .range((Excluded((cursor.created_at, cursor.id)), Unbounded))
+                .take(limit)
+                .map(|(_, block)| block.clone())
+                .collect(),
+            None => blocks.values().take(limit).cloned().collect(),
+        })
+    }
+
+    async fn append_summary_usage(
+        &self,
+        interaction_id: Uuid,
+        agent_id: Option<Uuid>,
+        usage: &TokenUsage,
+        created_at: DateTime<Utc>,
+    ) -> Result<(), MyselfError> {
+        let mut state = self.write()?;
+
+        if !state.interactions.contains_key(&interaction_id) {
+            return Err(MyselfError::NotFound(format!(
+                "interaction {}",
+                interaction_id
+            )));
+        }
+
+        state.check_agent(agent_id)?;
+
+        state.summary_usage.push(SummaryUsage {
+            created_at,
+            interaction_id,
+            agent_id,
+            usage: usage.clone(),
+        });
+
+        Ok(())
+    }
+
+    async fn get_interaction_usage(
+        &self,
+        interaction_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        let state = self.read()?;
+
+        Ok(model_usage(
+            state
+                .blocks
+                .get(&interaction_id)
+                .into_iter()
+                .flat_map(|blocks| blocks_since(blocks, since))
+                .filter_map(|block| block.usage.as_ref())
+                .chain(
+                    state
+                        .summary_usage_since(since)
+                        .filter(|summary| summary.interaction_id == interaction_id)
+                        .map(|summary| &summary.usage),
+                ),
+        ))
+    }
+
+    async fn get_agent_usage(
+        &self,
+        agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        let state = self.read()?;
+
+        Ok(model_usage(
+            state
+                .blocks
+                .values()
+                .flat_map(|blocks| blocks_since(blocks, since))
+                .filter(|block| block.agent_id == Some(agent_id))
+                .filter_map(|block| block.usage.as_ref())
+                .chain(
+                    state
+                        .summary_usage_since(since)
+                        .filter(|summary| summary.agent_id == Some(agent_id))
+                        .map(|summary| &summary.usage),
+                ),
+        ))
+    }
+
+    async fn search_blocks(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        let state = self.read()?;
+
+        let Some(blocks) = state.blocks.get(&interaction_id) else {
+            return Ok(Vec::new());
+        };
+
+        let candidates = match before {
+            Some(cursor) => blocks.range(..(cursor.created_at, cursor.id)),
+            None => blocks.range(..),
+        }
+        .filter_map(|(_, block)| {
+            block
+                .embedding
+                .clone()
+                .map(|embedding| (block.clone(), embedding))
+        });
+
+        Ok(most_similar(candidates, embedding, limit))
+    }
+
+    async fn flush_long_term_memory(&self, interaction_id: Uuid) -> Result<(), MyselfError> {
+        self.write()?.blocks.remove(&interaction_id);
+
+        Ok(())
+    }
+
+    async fn get_meta(&self, agent_id: Uuid) -> Result<Option<Meta>, MyselfError> {
+        Ok(self.read()?.meta.get(&agent_id).cloned())
+    }
+
+    async fn insert_meta(&self, meta: Meta) -> Result<Meta, MyselfError> {
+        let mut state = self.write()?;
+
+        if !state.agents.contains_key(&meta.agent_id) {
+            return Err(MyselfError::NotFound(format!("agent {}", meta.agent_id)));
+        }
+
+        state.meta.insert(meta.agent_id, meta.clone());
+
+        Ok(meta)
+    }
+
+    async fn set_default_interaction(
+        &self,
+        meta_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<Option<Meta>, MyselfError> {
+        Ok(self
+            .write()?
+            .meta
+            .values_mut()
+            .find(|meta| meta.id == meta_id)
+            .map(|meta| {
+                meta.default_interaction_id = interaction_id;
+                meta.updated_at = Utc::now();
+                meta.clone()
+            }))
+    }
+
+    async fn insert_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError> {
+        let mut state = self.write()?;
+
+        state.check_external_id(&participant)?;
+        state
+            .participants
+            .insert(participant.id, participant.clone());
+
+        Ok(participant)
+    }
+
+    async fn get_participant(&self, id: Uuid) -> Result<Option<Participant>, MyselfError> {
+        Ok(self.read()?.participants.get(&id).cloned())
+    }
+
+    async fn get_participant_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Result<Option<Participant>, MyselfError> {
+        Ok(self
+            .read()?
+            .participants
+            .values()
+            .find(|participant| participant.external_id.as_deref() == Some(external_id))
+            .cloned())
+    }
+
+    async fn list_participants(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Participant>, MyselfError> {
+        let mut participants = self
+            .read()?
+            .participants
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        participants.sort_by_key(|participant| (participant.created_at, participant.id));
+
+        Ok(participants.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn update_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Option<Participant>, MyselfError> {
+        let mut state = self.write()?;
+
+        state.check_external_id(&participant)?;
+
+        Ok(state.participants.get_mut(&participant.id).map(|stored| {
+            *stored = Participant {
+                created_at: stored.created_at,
+                ..participant
+            };
+            stored.clone()
+        }))
+    }
+
+    async fn get_participant_interactions(
+        &self,
+        participant_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        let state = self.read()?;
+
+        let interactions = state
+            .interactions
+            .values()
+            .filter(|interaction| {
+                interaction.participant_id == Some(participant_id)
+                    || state.blocks.get(&interaction.id).is_some_and(|blocks| {
+                        blocks
+                            .values()
+                            .any(|block| block.participant_id == Some(participant_id))
+                    })
+            })
+            .cloned()
+            .collect();
+
+        Ok(page_of_interactions(interactions, options))
+    }
+
+    async fn insert_member(
+        &self,
+        member: InteractionMember,
+    ) -> Result<InteractionMember, MyselfError> {
+        let mut state = self.write()?;
+
+        if !state.interactions.contains_key(&member.interaction_id) {
+            return Err(MyselfError::NotFound(format!(
+                "interaction {}",
+                member.interaction_id
+            )));
+        }
+
+        match member.member {
+            Member::Participant(id) => state.check_participant(Some(id))?,
+            Member::Agent(id) if !state.agents.contains_key(&id) => {
+                return Err(MyselfError::NotFound(format!("agent {}", id)));
+            }
+            Member::Agent(_) => {}
+        }
+
+        let members = state.members.entry(member.interaction_id).or_default();
+
+        if members.iter().any(|other| other.member == member.member) {
+            return Err(MyselfError::Configuration(format!(
+                "{} is already a member of interaction {}",
+                member.member, member.interaction_id
+            )));
+        }
+
+        members.push(member.clone());
+
+        Ok(member)
+    }
+
+    async fn get_members(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<Vec<InteractionMember>, MyselfError> {
+        let mut members = self
+            .read()?
+            .members
+            .get(&interaction_id)
+            .cloned()
+            .unwrap_or_default();
+
+        members.sort_by_key(|member| (member.created_at, member.id));
+
+        Ok(members)
+    }
+
+    async fn delete_member(
+        &self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<bool, MyselfError> {
+        let mut state = self.write()?;
+
+        let Some(members) = state.members.get_mut(&interaction_id) else {
+            return Ok(false);
+        };
+
+        let before = members.len();
+        members.retain(|other| other.member != member);
+
+        Ok(members.len() < before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{
+        backend::mock::MockBackend, database::memory::MemoryEngine,
+        sdk::interaction::InteractionBlockRole,
+    };
+
+    fn agent_record() -> AgentRecord {
+        AgentRecord {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            name: "Tester".to_string(),
+            default_user_name: "User".to_string(),
+            default_constitution: "Be brief.".to_string(),
+            default_memory_size: 10,
+            llm_configuration: None,
+            quota: Quota::default(),
+        }
+    }
+
+    fn interaction_record(agent_id: Option<Uuid>) -> InteractionRecord {
+        InteractionRecord {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            user_name: "User".to_string(),
+            constitution: "Be brief.".to_string(),
+            short_term_memory: String::new(),
+            summarized_until: None,
+            long_term_memory_size: 10,
+            llm_overrides: LLMOverrides::default(),
+            quota: Quota::default(),
+            prompt_template: None,
+            agent_id,
+            participant_id: None,
+        }
+    }
+
+    // Blocks one second apart, so their order does not depend on the clock.
+    async fn append_blocks(store: &dyn MemoryStore, interaction_id: Uuid, count: usize) {
+        let start = Utc::now();
+
+        for i in 0..count {
+            let block = InteractionBlock {
+                created_at: start + Duration::seconds(i as i64),
+                ..InteractionBlock::new_user(interaction_id, format!("message {}", i), None)
+            };
+
+            store.append_block(interaction_id, &block).await.unwrap();
+        }
+    }
+
+    fn contents(blocks: &[InteractionBlock]) -> Vec<&str> {
+        blocks.iter().map(|block| block.content.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn interactions_and_blocks_round_trip() {
+        let store = InMemoryStore::new();
+        let agent = store.insert_agent(agent_record()).await.unwrap();
+        let interaction = store
+            .insert_interaction(interaction_record(Some(agent.id)))
+            .await
+            .unwrap();
+
+        append_blocks(&store, interaction.id, 3).await;
+
+        let blocks = store
+            .get_long_term_memory(interaction.id, None, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(contents(&blocks), ["message 0", "message 1", "message 2"]);
+        assert!(blocks
+            .iter()
+            .all(|block| block.interaction_id == interaction.id
+                && matches!(block.role, InteractionBlockRole::User)));
+
+        let stored = store
+            .get_interaction(interaction.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.updated_at, blocks[2].created_at);
+
+        let updated = store
+            .update_constitution(interaction.id, "Be verbose.".to_string(), Utc::now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.constitution, "Be verbose.");
+
+        let listed = store
+            .get_agent_interactions(agent.id, &InteractionsQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, interaction.id);
+    }
+
+    #[tokio::test]
+    async fn interactions_need_an_existing_agent() {
+        let store = InMemoryStore::new();
+
+        let result = store
+            .insert_interaction(interaction_record(Some(Uuid::new_v4())))
+            .await;
+
+        assert!(matches!(result, Err(MyselfError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn blocks_need_an_existing_interaction() {
+        let store = InMemoryStore::new();
+        let id = Uuid::new_v4();
+
+        let result = store
+            .append_block(id, &InteractionBlock::new_user(id, "hi".to_string(), None))
+            .await;
+
+        assert!(matches!(result, Err(MyselfError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn long_term_memory_pages_backwards_and_forwards() {
+        let store = InMemoryStore::new();
+        let interaction = store
+            .insert_interaction(interaction_record(None))
+            .await
+            .unwrap();
+
+        append_blocks(&store, interaction.id, 5).await;
+
+        let newest = store
+            .get_long_term_memory(interaction.id, None, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&newest), ["message 3", "message 4"]);
+
+        let older = store
+            .get_long_term_memory(interaction.id, Some(newest[0].cursor()), 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&older), ["message 1", "message 2"]);
+
+        let after = store
+            .get_blocks_after(interaction.id, Some(older[0].cursor()), 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&after), ["message 2", "message 3"]);
+
+        let first = store
+            .get_blocks_after(interaction.id, None, 1)
+            .await
+            .unwrap();
+        assert_eq!(contents(&first), ["message 0"]);
+    }
+
+    #[tokio::test]
+    async fn history_pages_until_the_first_block() {
+        let memory_engine = MemoryEngine::<MockBackend>::in_memory();
+        let store = memory_engine.store();
+        let interaction = store
+            .insert_interaction(interaction_record(None))
+            .await
+            .unwrap();
+
+        append_blocks(store.as_ref(), interaction.id, 5).await;
+
+        let page = memory_engine
+            .get_interaction_history(interaction.id, None, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&page.blocks), ["message 3", "message 4"]);
+        assert_eq!(page.next_cursor, Some(page.blocks[0].cursor()));
+
+        let page = memory_engine
+            .get_interaction_history(interaction.id, page.next_cursor, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&page.blocks), ["message 1", "message 2"]);
+        assert!(page.next_cursor.is_some());
+
+        let page = memory_engine
+            .get_interaction_history(interaction.id, page.next_cursor, 2)
+            .await
+            .unwrap();
+        assert_eq!(contents(&page.blocks), ["message 0"]);
+        assert_eq!(page.next_cursor, None);
+
+        // A page that ends exactly at the first block has nothing left to page to.
+        let page = memory_engine
+            .get_interaction_history(interaction.id, None, 5)
+            .await
+            .unwrap();
+        assert_eq!(page.blocks.len(), 5);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn meta_points_at_the_default_interaction() {
+        let store = InMemoryStore::new();
+        let agent = store.insert_agent(agent_record()).await.unwrap();
+        let first = store
+            .insert_interaction(interaction_record(Some(agent.id)))
+            .await
+            .unwrap();
+        let second = store
+            .insert_interaction(interaction_record(Some(agent.id)))
+            .await
+            .unwrap();
+
+        assert!(store.get_meta(agent.id).await.unwrap().is_none());
+
+        let meta = store
+            .insert_meta(Meta {
+                agent_id: agent.id,
+                default_interaction_id: first.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let updated = store
+            .set_default_interaction(meta.id, second.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.default_interaction_id, second.id);
+
+        let stored = store.get_meta(agent.id).await.unwrap().unwrap();
+        assert_eq!(stored.default_interaction_id, second.id);
+
+        let missing = store
+            .insert_meta(Meta {
+                agent_id: Uuid::new_v4(),
+                default_interaction_id: first.id,
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(missing, Err(MyselfError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn deleting_an_agent_deletes_what_it_owns() {
+        let store = InMemoryStore::new();
+        let agent = store.insert_agent(agent_record()).await.unwrap();
+        let other = store.insert_agent(agent_record()).await.unwrap();
+
+        let owned = store
+            .insert_interaction(interaction_record(Some(agent.id)))
+            .await
+            .unwrap();
+        let kept = store
+            .insert_interaction(interaction_record(Some(other.id)))
+            .await
+            .unwrap();
+
+        append_blocks(&store, owned.id, 2).await;
+        append_blocks(&store, kept.id, 2).await;
+
+        store
+            .insert_meta(Meta {
+                agent_id: agent.id,
+                default_interaction_id: owned.id,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        for member in [Member::Agent(agent.id), Member::Agent(other.id)] {
+            store
+                .insert_member(InteractionMember::new(owned.id, member))
+                .await
+                .unwrap();
+            store
+                .insert_member(InteractionMember::new(kept.id, member))
+                .await
+                .unwrap();
+        }
+
+        assert!(store.delete_agent(agent.id).await.unwrap());
+        assert!(!store.delete_agent(agent.id).await.unwrap());
+
+        assert!(store.get_agent(agent.id).await.unwrap().is_none());
+        assert!(store.get_meta(agent.id).await.unwrap().is_none());
+        assert!(store.get_interaction(owned.id).await.unwrap().is_none());
+        assert!(store
+            .get_long_term_memory(owned.id, None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store.get_members(owned.id).await.unwrap().is_empty());
+
+        // The other agent's interaction survives, without the deleted member.
+        assert!(store.get_interaction(kept.id).await.unwrap().is_some());
+        assert_eq!(
+            store
+                .get_long_term_memory(kept.id, None, 10)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        let members = store.get_members(kept.id).await.unwrap();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].member, Member::Agent(other.id));
+    }
+
+    #[tokio::test]
+    async fn flushing_long_term_memory_keeps_the_interaction() {
+        let store = InMemoryStore::new();
+        let interaction = store
+            .insert_interaction(interaction_record(None))
+            .await
+            .unwrap();
+
+        append_blocks(&store, interaction.id, 3).await;
+        store.flush_long_term_memory(interaction.id).await.unwrap();
+
+        assert!(store
+            .get_long_term_memory(interaction.id, None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .get_interaction(interaction.id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
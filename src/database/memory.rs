@@ -1,86 +1,103 @@
-use std::{marker::PhantomData, str::FromStr};
+use std::{marker::PhantomData, sync::Arc};
 
-use chrono::Utc;
-use sqlx::{postgres::PgPool, query};
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 
 use crate::{
     // agent::{Agent, DefaultInteraction},
     backend::core::AgentBackend,
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMOverrides},
     sdk::agent::{Agent, DefaultInteraction},
+    sdk::group::{InteractionMember, Member},
     sdk::interaction::{
-        Interaction, InteractionBlock, InteractionBlockRole, Meta, WithAgent, WithoutAgent,
+        HistoryCursor, HistoryPage, Interaction, InteractionBlock, InteractionsQuery, Meta,
+        WithAgent, WithoutAgent,
     },
+    sdk::participant::Participant,
+    sdk::quota::{Allowance, Quota, QuotaScope},
+    sdk::template::PromptTemplate,
+    sdk::usage::{PriceTable, TokenUsage, UsageReport},
 };
 
-use super::{engine::new_postgres_pool, models::migrate_database_with_pg_pool};
+use super::{
+    in_memory::InMemoryStore,
+    postgres::PostgresStore,
+    store::{AgentRecord, InteractionRecord, MemoryStore},
+};
 
 #[derive(Debug, Clone)]
 pub struct MemoryEngine<Backend>
 where
     Backend: AgentBackend + Sized + Default + Clone,
 {
-    pool: PgPool,
-    phantom: PhantomData<Backend>,
+    store: Arc<dyn MemoryStore>,
+    // The engine never holds a backend, so it is Send + Sync whatever the backend is.
+    phantom: PhantomData<fn() -> Backend>,
 }
 
 impl<Backend> MemoryEngine<Backend>
 where
     Backend: AgentBackend + Sized + Default + Clone,
 {
-    pub async fn new(database_url: String) -> Self {
-        let pool = new_postgres_pool(database_url).await;
+    pub async fn new(database_url: String) -> Result<Self, MyselfError> {
+        if database_url.starts_with("sqlite:") {
+            return Self::new_sqlite(database_url).await;
+        }
 
-        migrate_database_with_pg_pool(&pool).await;
+        let store = PostgresStore::new(database_url).await?;
 
-        Self {
-            pool,
-            phantom: PhantomData,
-        }
+        Ok(Self::with_store(store))
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub async fn new_sqlite(database_url: String) -> Result<Self, MyselfError> {
+        let store = super::sqlite::SqliteStore::new(database_url).await?;
+
+        Ok(Self::with_store(store))
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    pub async fn new_sqlite(_database_url: String) -> Result<Self, MyselfError> {
+        Err(MyselfError::Configuration(
+            "SQLite support requires the `sqlite` feature".to_string(),
+        ))
     }
 
-    pub async fn new_defaults() -> Self {
+    pub async fn new_defaults() -> Result<Self, MyselfError> {
         let database_url =
             std::env::var("DATABASE_URL").unwrap_or("sqlite://sqlite.db".to_string());
 
         Self::new(database_url).await
     }
 
+    pub fn with_store(store: impl MemoryStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn in_memory() -> Self {
+        Self::with_store(InMemoryStore::new())
+    }
+
+    pub fn store(&self) -> Arc<dyn MemoryStore> {
+        self.store.clone()
+    }
+
+    pub async fn schema_version(&self) -> Result<Option<i64>, MyselfError> {
+        self.store.schema_version().await
+    }
+
     pub async fn new_interaction(
         &mut self,
         user_name: String,
         constitution: String,
         memory_size: usize,
-    ) -> Interaction<Backend, WithoutAgent> {
-        let interaction = Interaction::<Backend>::new(user_name, constitution, memory_size);
-        let res = query!(
-            r#"
-            INSERT INTO interactions (id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory)
-            VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory
-            "#,
-            interaction.id,
-            interaction.created_at.naive_utc(),
-            interaction.updated_at.naive_utc(),
-            interaction.user_name,
-            interaction.long_term_memory_size as i32,
-            interaction.constitution,
-            interaction.short_term_memory,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap();
-
-        Interaction::<Backend, WithoutAgent> {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            user_name: res.user_name,
-            long_term_memory_size: res.default_long_term_memory_size as usize,
-            short_term_memory: res.short_term_memory,
-            constitution: res.constitution,
-            state: PhantomData,
-            agent: None,
-        }
+    ) -> Result<Interaction<Backend, WithoutAgent>, MyselfError> {
+        self.insert_interaction(user_name, constitution, memory_size, None, None)
+            .await
     }
 
     pub async fn new_interaction_with_agent(
@@ -89,290 +106,646 @@ where
         constitution: String,
         memory_size: usize,
         agent: &Agent<Backend>,
-    ) -> Interaction<Backend, WithAgent> {
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
         let mut interaction = self
-            .new_interaction(user_name, constitution, memory_size)
-            .await;
+            .insert_interaction(user_name, constitution, memory_size, Some(agent.id), None)
+            .await?;
 
-        interaction.with_agent(agent.to_owned()) // TODO: Check if it can be optimized
+        Ok(interaction.with_agent(agent.to_owned())) // TODO: Check if it can be optimized
+    }
+
+    // An interaction held with `participant`, who is its user.
+    pub async fn new_participant_interaction(
+        &mut self,
+        participant: &Participant,
+        constitution: String,
+        memory_size: usize,
+        agent: &Agent<Backend>,
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        let mut interaction = self
+            .insert_interaction(
+                participant.display_name.clone(),
+                constitution,
+                memory_size,
+                Some(agent.id),
+                Some(participant.id),
+            )
+            .await?;
+
+        Ok(interaction.with_agent(agent.to_owned()))
+    }
+
+    async fn insert_interaction(
+        &mut self,
+        user_name: String,
+        constitution: String,
+        memory_size: usize,
+        agent_id: Option<Uuid>,
+        participant_id: Option<Uuid>,
+    ) -> Result<Interaction<Backend, WithoutAgent>, MyselfError> {
+        let interaction = Interaction::<Backend>::new(user_name, constitution, memory_size);
+
+        let record = self
+            .store
+            .insert_interaction(InteractionRecord {
+                id: interaction.id,
+                created_at: interaction.created_at,
+                updated_at: interaction.updated_at,
+                user_name: interaction.user_name,
+                constitution: interaction.constitution,
+                short_term_memory: interaction.short_term_memory,
+                summarized_until: interaction.summarized_until,
+                long_term_memory_size: interaction.long_term_memory_size,
+                llm_overrides: interaction.llm_overrides,
+                quota: interaction.quota,
+                prompt_template: interaction.prompt_template,
+                agent_id,
+                participant_id,
+            })
+            .await?;
+
+        Ok(record.into())
     }
 
     pub async fn update_constitution(
         &mut self,
-        _id: Uuid,
-        _constitution: String,
-    ) -> Interaction<Backend> {
-        todo!()
+        id: Uuid,
+        constitution: String,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.store
+            .update_constitution(id, constitution, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", id)))
+    }
+
+    pub async fn set_llm_overrides(
+        &mut self,
+        interaction_id: Uuid,
+        overrides: LLMOverrides,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        overrides.validate()?;
+
+        self.store
+            .set_llm_overrides(interaction_id, overrides, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))
+    }
+
+    pub async fn set_quota(
+        &mut self,
+        interaction_id: Uuid,
+        quota: Quota,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        quota.validate()?;
+
+        self.store
+            .set_quota(interaction_id, quota, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))
+    }
+
+    // `None` makes the interaction use its agent's template again.
+    pub async fn set_prompt_template(
+        &mut self,
+        interaction_id: Uuid,
+        template: Option<PromptTemplate>,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        if let Some(template) = &template {
+            template.validate()?;
+        }
+
+        self.store
+            .set_prompt_template(interaction_id, template, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))
+    }
+
+    pub async fn set_summary(
+        &mut self,
+        interaction_id: Uuid,
+        summary: String,
+        summarized_until: HistoryCursor,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.store
+            .set_summary(interaction_id, summary, summarized_until, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))
+    }
+
+    // Counts the usage of a summarization of the interaction made for
+    // `agent_id`.
+    pub async fn append_summary_usage(
+        &mut self,
+        interaction_id: Uuid,
+        agent_id: Uuid,
+        usage: &TokenUsage,
+    ) -> Result<(), MyselfError> {
+        self.store
+            .append_summary_usage(interaction_id, Some(agent_id), usage, Utc::now())
+            .await
     }
 
     pub async fn append_to_long_term_memory(
         &mut self,
         interaction_id: Uuid,
         interaction_block: &InteractionBlock,
-    ) -> InteractionBlock {
-        query!(
-            r#"
-            INSERT INTO interaction_blocks (id, created_at, updated_at, interaction_id, role, content, name)
-            VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, created_at, updated_at, interaction_id, role, content, name
-            "#,
-            interaction_block.id,
-            interaction_block.created_at.naive_utc(),
-            interaction_block.updated_at.naive_utc(),
-            interaction_id,
-            interaction_block.role.as_str(),
-            interaction_block.content,
-            interaction_block.name,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map(|res| InteractionBlock {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            interaction_id: res.interaction_id,
-            role: InteractionBlockRole::from_str(&res.role).unwrap(),
-            content: res.content,
-            name: res.name,
-        })
-        .unwrap()
+    ) -> Result<InteractionBlock, MyselfError> {
+        self.store
+            .append_block(interaction_id, interaction_block)
+            .await
     }
 
     pub async fn set_short_term_memory(
         &mut self,
-        _interaction_id: Uuid,
-        _memory: String,
-    ) -> Interaction<Backend> {
-        todo!()
-    }
-
-    pub async fn get_meta_with_agent(&mut self, agent: &mut Agent<Backend>) -> Meta {
-        let meta_exists = query!(
-            r#"
-            SELECT EXISTS(SELECT 1 FROM meta)
-            "#
-        )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap()
-        .exists
-        .unwrap();
-
-        if !meta_exists {
-            let default_interaction = self
-                .new_interaction_with_agent(
-                    agent.default_interaction.user_name.clone(),
-                    agent.default_interaction.constitution.clone(),
-                    agent.default_interaction.memory_size,
-                    agent,
-                )
-                .await;
-
-            query!(
-                r#"
-                INSERT INTO meta (id, created_at, updated_at, default_interaction_id)
-                VALUES ($1, $2, $3, $4) RETURNING id, created_at, updated_at, default_interaction_id
-                "#,
-                Uuid::new_v4(),
-                Utc::now().naive_utc(),
-                Utc::now().naive_utc(),
-                default_interaction.id,
+        interaction_id: Uuid,
+        memory: String,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.store
+            .set_short_term_memory(interaction_id, memory, Utc::now())
+            .await?
+            .map(Interaction::from)
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))
+    }
+
+    // The agent's meta, created along with a default interaction on first use.
+    pub async fn get_meta_with_agent(
+        &mut self,
+        agent: &mut Agent<Backend>,
+    ) -> Result<Meta, MyselfError> {
+        if let Some(meta) = self.store.get_meta(agent.id).await? {
+            return Ok(meta);
+        }
+
+        let default_interaction = self
+            .new_interaction_with_agent(
+                agent.default_interaction.user_name.clone(),
+                agent.default_interaction.constitution.clone(),
+                agent.default_interaction.memory_size,
+                agent,
             )
-            .fetch_one(&self.pool)
-            .await
-            .map(|res| Meta {
-                id: res.id,
-                created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-                updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-                default_interaction_id: res.default_interaction_id.unwrap(),
+            .await?;
+
+        self.store
+            .insert_meta(Meta {
+                agent_id: agent.id,
+                default_interaction_id: default_interaction.id,
+                ..Default::default()
             })
-            .unwrap()
-        } else {
-            query!(
-                r#"
-                SELECT id, created_at, updated_at, default_interaction_id
-                FROM meta
-                "#
-            )
-            .fetch_one(&self.pool)
             .await
-            .map(|res| Meta {
-                id: res.id,
-                created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-                updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-                default_interaction_id: res.default_interaction_id.unwrap(),
-            })
-            .unwrap()
-        }
     }
 
-    pub async fn get_meta(&mut self) -> Meta {
-        query!(
-            r#"
-            SELECT id, created_at, updated_at, default_interaction_id
-            FROM meta
-            "#
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map(|res| Meta {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            default_interaction_id: res.default_interaction_id.unwrap(),
-        })
-        .unwrap()
+    pub async fn get_meta(&mut self, agent_id: Uuid) -> Result<Meta, MyselfError> {
+        self.store
+            .get_meta(agent_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("meta of agent {}", agent_id)))
     }
 
     pub async fn get_interaction(
         &mut self,
         id: Uuid,
-    ) -> Option<Interaction<Backend, WithoutAgent>> {
-        let res = query!(
-            r#"
-            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory
-            FROM interactions
-            WHERE id = $1
-            "#,
-            id,
-        )
-        .fetch_optional(&self.pool)
-        .await
-        .unwrap();
-
-        res.map(|res| Interaction::<Backend, WithoutAgent> {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            user_name: res.user_name,
-            short_term_memory: res.short_term_memory,
-            long_term_memory_size: res.default_long_term_memory_size as usize,
-            constitution: res.constitution,
-            state: PhantomData,
-            agent: None,
-        })
+    ) -> Result<Option<Interaction<Backend, WithoutAgent>>, MyselfError> {
+        Ok(self.store.get_interaction(id).await?.map(Interaction::from))
     }
 
-    pub async fn set_default_interaction(&mut self, id: Uuid) -> Meta {
-        query!(
-            r#"
-            UPDATE meta
-            SET default_interaction_id = $1
-            WHERE id = $2
-            RETURNING id, created_at, updated_at, default_interaction_id
-            "#,
-            id,
-            self.get_meta().await.id,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map(|res| Meta {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            default_interaction_id: res.default_interaction_id.unwrap(),
-        })
-        .unwrap()
+    // Makes `interaction_id`, which must belong to the agent, its default
+    // interaction.
+    pub async fn set_default_interaction(
+        &mut self,
+        agent_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<Meta, MyselfError> {
+        let interaction = self
+            .store
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        if interaction.agent_id != Some(agent_id) {
+            return Err(MyselfError::Configuration(format!(
+                "interaction {} does not belong to agent {}",
+                interaction_id, agent_id
+            )));
+        }
+
+        match self.store.get_meta(agent_id).await? {
+            Some(meta) => self
+                .store
+                .set_default_interaction(meta.id, interaction_id)
+                .await?
+                .ok_or_else(|| MyselfError::NotFound(format!("meta of agent {}", agent_id))),
+            None => {
+                self.store
+                    .insert_meta(Meta {
+                        agent_id,
+                        default_interaction_id: interaction_id,
+                        ..Default::default()
+                    })
+                    .await
+            }
+        }
     }
 
     pub async fn get_or_create_default_interaction(
         &mut self,
         agent: &mut Agent<Backend>,
-    ) -> Interaction<Backend, WithAgent> {
-        let interaction_id = self.get_meta_with_agent(agent).await.default_interaction_id;
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        let interaction_id = self
+            .get_meta_with_agent(agent)
+            .await?
+            .default_interaction_id;
 
-        self.get_interaction(interaction_id)
-            .await
-            .unwrap()
-            .with_agent(agent.clone())
+        Ok(self
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?
+            .with_agent(agent.clone()))
     }
 
-    pub async fn get_all_interactions(&mut self) -> Vec<Interaction<Backend, WithoutAgent>> {
-        todo!()
+    pub async fn get_all_interactions(
+        &mut self,
+        agent_id: Uuid,
+        options: InteractionsQuery,
+    ) -> Result<Vec<Interaction<Backend, WithoutAgent>>, MyselfError> {
+        Ok(self
+            .store
+            .get_agent_interactions(agent_id, &options)
+            .await?
+            .into_iter()
+            .map(Interaction::from)
+            .collect())
     }
 
     pub async fn new_agent(
         &mut self,
         name: String,
-        default_user_name: String,
-        default_constitution: String,
-        default_memory_size: usize,
+        default_interaction: DefaultInteraction,
+        llm_configuration: Option<LLMConfiguration>,
+        quota: Quota,
         llm_engine: Backend,
         memory_engine: MemoryEngine<Backend>,
-    ) -> Agent<Backend>
+    ) -> Result<Agent<Backend>, MyselfError>
     where
         Backend: AgentBackend + Sized + Default + Clone,
     {
-        let new_id = Uuid::new_v4();
-        let res = query!(
-            r#"
-            INSERT INTO agents (id, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size)
-            VALUES ($1, $2, $3, $4, $5) RETURNING id, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size
-            "#,
-            new_id,
-            name,
-            default_user_name,
-            default_constitution,
-            default_memory_size as i32,
-        )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap();
-
-        Agent::new(
-            res.id,
-            res.name,
+        let res = self
+            .store
+            .insert_agent(AgentRecord {
+                id: Uuid::new_v4(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                name,
+                default_user_name: default_interaction.user_name,
+                default_constitution: default_interaction.constitution,
+                default_memory_size: default_interaction.memory_size,
+                llm_configuration,
+                quota,
+            })
+            .await?;
+
+        Ok(Self::agent_from_record(res, llm_engine, memory_engine))
+    }
+
+    // An agent with the stored defaults, LLM configuration and quota of
+    // `record`.
+    pub fn agent_from_record(
+        record: AgentRecord,
+        llm_engine: Backend,
+        memory_engine: MemoryEngine<Backend>,
+    ) -> Agent<Backend> {
+        let mut agent = Agent::new(
+            record.id,
+            record.name,
             DefaultInteraction {
-                user_name: res.default_interaction_user_name,
-                constitution: res.default_interaction_constitution,
-                memory_size: res.default_interaction_memory_size as usize,
+                user_name: record.default_user_name,
+                constitution: record.default_constitution,
+                memory_size: record.default_memory_size,
             },
             llm_engine,
             memory_engine,
-        )
+        );
+
+        agent.llm_configuration = record.llm_configuration;
+        agent.quota = record.quota;
+
+        agent
+    }
+
+    pub async fn get_agent(&self, id: Uuid) -> Result<Option<AgentRecord>, MyselfError> {
+        self.store.get_agent(id).await
+    }
+
+    // The oldest agent named `name`.
+    pub async fn get_agent_by_name(&self, name: &str) -> Result<Option<AgentRecord>, MyselfError> {
+        self.store.get_agent_by_name(name).await
+    }
+
+    pub async fn list_agents(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<AgentRecord>, MyselfError> {
+        self.store.list_agents(limit, offset).await
+    }
+
+    pub async fn update_agent(&mut self, agent: AgentRecord) -> Result<AgentRecord, MyselfError> {
+        if let Some(configuration) = &agent.llm_configuration {
+            configuration.validate()?;
+        }
+
+        agent.quota.validate()?;
+
+        let id = agent.id;
+
+        self.store
+            .update_agent(AgentRecord {
+                updated_at: Utc::now(),
+                ..agent
+            })
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("agent {}", id)))
+    }
+
+    // Deletes the agent with all its interactions.
+    pub async fn delete_agent(&mut self, id: Uuid) -> Result<(), MyselfError> {
+        if !self.store.delete_agent(id).await? {
+            return Err(MyselfError::NotFound(format!("agent {}", id)));
+        }
+
+        Ok(())
+    }
+
+    pub async fn new_participant(
+        &mut self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError> {
+        participant.validate()?;
+
+        self.store.insert_participant(participant).await
+    }
+
+    pub async fn get_participant(&self, id: Uuid) -> Result<Option<Participant>, MyselfError> {
+        self.store.get_participant(id).await
+    }
+
+    pub async fn get_participant_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Result<Option<Participant>, MyselfError> {
+        self.store.get_participant_by_external_id(external_id).await
+    }
+
+    // The participant known as `external_id`, created with `display_name` if
+    // there is none yet. An existing participant keeps its display name.
+    pub async fn get_or_create_participant(
+        &mut self,
+        external_id: &str,
+        display_name: &str,
+    ) -> Result<Participant, MyselfError> {
+        if let Some(participant) = self.get_participant_by_external_id(external_id).await? {
+            return Ok(participant);
+        }
+
+        self.new_participant(Participant::new(display_name).external_id(external_id))
+            .await
+    }
+
+    pub async fn list_participants(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Participant>, MyselfError> {
+        self.store.list_participants(limit, offset).await
+    }
+
+    pub async fn update_participant(
+        &mut self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError> {
+        participant.validate()?;
+
+        let id = participant.id;
+
+        self.store
+            .update_participant(Participant {
+                updated_at: Utc::now(),
+                ..participant
+            })
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("participant {}", id)))
+    }
+
+    // Interactions held with the participant or in which they wrote, of any
+    // agent.
+    pub async fn get_participant_interactions(
+        &self,
+        participant_id: Uuid,
+        options: InteractionsQuery,
+    ) -> Result<Vec<Interaction<Backend, WithoutAgent>>, MyselfError> {
+        Ok(self
+            .store
+            .get_participant_interactions(participant_id, &options)
+            .await?
+            .into_iter()
+            .map(Interaction::from)
+            .collect())
+    }
+
+    // Adds `member` to the interaction, doing nothing if it already is one.
+    pub async fn add_interaction_member(
+        &mut self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<InteractionMember, MyselfError> {
+        if let Some(existing) = self
+            .get_interaction_members(interaction_id)
+            .await?
+            .into_iter()
+            .find(|existing| existing.member == member)
+        {
+            return Ok(existing);
+        }
+
+        self.store
+            .insert_member(InteractionMember::new(interaction_id, member))
+            .await
+    }
+
+    pub async fn remove_interaction_member(
+        &mut self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<(), MyselfError> {
+        if !self.store.delete_member(interaction_id, member).await? {
+            return Err(MyselfError::NotFound(format!(
+                "{} in interaction {}",
+                member, interaction_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Members in the order they joined. Interactions that are not group
+    // interactions have none.
+    pub async fn get_interaction_members(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<Vec<InteractionMember>, MyselfError> {
+        self.store.get_members(interaction_id).await
     }
 
     pub async fn get_interaction_long_term_memory(
         &self,
         interaction_id: Uuid,
         limit: usize,
-    ) -> Vec<InteractionBlock> {
-        query!(
-            r#"
-            SELECT id, created_at, updated_at, name, interaction_id, role, content
-            FROM interaction_blocks
-            WHERE interaction_id = $1
-            ORDER BY created_at ASC
-            LIMIT $2
-            "#,
-            interaction_id,
-            limit as i64,
-        )
-        .fetch_all(&self.pool)
-        .await
-        .unwrap()
-        .iter()
-        .map(|res| InteractionBlock {
-            id: res.id,
-            created_at: res.created_at.and_local_timezone(Utc).unwrap(),
-            updated_at: res.updated_at.and_local_timezone(Utc).unwrap(),
-            name: res.name.clone(),
-            interaction_id: res.interaction_id,
-            role: InteractionBlockRole::from_str(res.role.as_str()).unwrap(),
-            content: res.content.clone(),
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        self.store
+            .get_long_term_memory(interaction_id, None, limit)
+            .await
+    }
+
+    pub async fn get_interaction_history(
+        &self,
+        interaction_id: Uuid,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MyselfError> {
+        // Fetch one extra block to know whether there is anything left to page to.
+        let mut blocks = self
+            .store
+            .get_long_term_memory(interaction_id, before, limit + 1)
+            .await?;
+
+        let next_cursor = if blocks.len() > limit {
+            blocks.remove(0);
+            blocks.first().map(InteractionBlock::cursor)
+        } else {
+            None
+        };
+
+        Ok(HistoryPage {
+            blocks,
+            next_cursor,
+        })
+    }
+
+    pub async fn get_interaction_blocks_after(
+        &self,
+        interaction_id: Uuid,
+        after: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        self.store
+            .get_blocks_after(interaction_id, after, limit)
+            .await
+    }
+
+    pub async fn search_interaction_blocks(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        self.store
+            .search_blocks(interaction_id, embedding, before, limit)
+            .await
+    }
+
+    pub async fn get_interaction_usage(
+        &self,
+        interaction_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<UsageReport, MyselfError> {
+        Ok(UsageReport {
+            models: self
+                .store
+                .get_interaction_usage(interaction_id, since)
+                .await?,
+        })
+    }
+
+    pub async fn get_agent_usage(
+        &self,
+        agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<UsageReport, MyselfError> {
+        Ok(UsageReport {
+            models: self.store.get_agent_usage(agent_id, since).await?,
         })
-        .collect()
-    }
-
-    pub async fn flush_interaction_long_term_memory(&self, interaction_id: Uuid) {
-        query!(
-            r#"
-            DELETE FROM interaction_blocks
-            WHERE interaction_id = $1
-            "#,
-            interaction_id,
-        )
-        .execute(&self.pool)
-        .await
-        .unwrap();
+    }
+
+    // What is left of `quota` for the agent or interaction `id`. Models
+    // without a price in `prices` count as free towards the spend limit.
+    pub async fn get_allowance(
+        &self,
+        scope: QuotaScope,
+        id: Uuid,
+        quota: &Quota,
+        prices: &PriceTable,
+    ) -> Result<Allowance, MyselfError> {
+        let now = Utc::now();
+        let mut allowance = Allowance::unlimited(scope);
+
+        if let Some(limit) = quota.tokens_per_day {
+            let usage = self
+                .get_usage(scope, id, Some(now - Duration::days(1)))
+                .await?;
+            allowance.tokens_today = Some(limit.saturating_sub(usage.total_tokens()));
+        }
+
+        if let Some(limit) = quota.requests_per_minute {
+            let usage = self
+                .get_usage(scope, id, Some(now - Duration::minutes(1)))
+                .await?;
+            allowance.requests_this_minute = Some(limit.saturating_sub(usage.responses()));
+        }
+
+        // Spend on a model without a price cannot be counted, so it fails the
+        // check rather than letting the limit be passed unnoticed.
+        if let Some(limit) = quota.max_cost {
+            let spent = self
+                .get_usage(scope, id, None)
+                .await?
+                .models
+                .iter()
+                .map(|usage| {
+                    usage.cost(prices).ok_or_else(|| {
+                        MyselfError::Configuration(format!(
+                            "max_cost needs a price for model {:?}",
+                            usage.model_name
+                        ))
+                    })
+                })
+                .sum::<Result<f64, _>>()?;
+            allowance.cost = Some((limit - spent).max(0.0));
+        }
+
+        Ok(allowance)
+    }
+
+    async fn get_usage(
+        &self,
+        scope: QuotaScope,
+        id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<UsageReport, MyselfError> {
+        match scope {
+            QuotaScope::Agent => self.get_agent_usage(id, since).await,
+            QuotaScope::Interaction => self.get_interaction_usage(id, since).await,
+        }
+    }
+
+    pub async fn flush_interaction_long_term_memory(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<(), MyselfError> {
+        self.store.flush_long_term_memory(interaction_id).await
     }
 }
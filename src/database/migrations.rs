@@ -0,0 +1,606 @@
+use chrono::Utc;
+use sqlx::{postgres::PgPool, query, query_scalar, Executor};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+// Migrations are applied in order and recorded in `schema_migrations`. Never edit
+// a released migration, append a new one with the next version instead.
+pub const POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: "
+CREATE TABLE IF NOT EXISTS agents (
+    id UUID PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    name TEXT NOT NULL,
+
+    default_interaction_user_name TEXT NOT NULL,
+    default_interaction_constitution TEXT NOT NULL,
+    default_interaction_memory_size INTEGER NOT NULL
+);
+
+
+CREATE TABLE IF NOT EXISTS interactions (
+    id UUID PRIMARY KEY,
+    
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+
+    user_name TEXT NOT NULL,
+    constitution TEXT NOT NULL,
+    
+    short_term_memory TEXT NOT NULL,
+    default_long_term_memory_size INTEGER NOT NULL,
+    
+    agent_id UUID REFERENCES agents(id)
+);
+
+
+CREATE TABLE IF NOT EXISTS interaction_blocks (
+    id UUID PRIMARY KEY,
+    
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    
+    interaction_id UUID NOT NULL REFERENCES interactions(id),
+    
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+
+    name TEXT
+);
+
+
+CREATE TABLE IF NOT EXISTS meta (
+    id UUID PRIMARY KEY,
+
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+
+    default_interaction_id UUID NOT NULL REFERENCES interactions(id)
+);
+",
+    },
+    Migration {
+        version: 2,
+        description: "index interaction blocks by recency",
+        sql: "
+CREATE INDEX IF NOT EXISTS interaction_blocks_recency_idx
+    ON interaction_blocks (interaction_id, created_at DESC, id DESC);
+",
+    },
+    Migration {
+        version: 3,
+        description: "track summarized interaction history",
+        sql: "
+ALTER TABLE interactions ADD COLUMN summarized_until_created_at TIMESTAMP;
+ALTER TABLE interactions ADD COLUMN summarized_until_id UUID;
+",
+    },
+    Migration {
+        version: 4,
+        description: "link tool calls to their results",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN tool_call_id TEXT;
+",
+    },
+    Migration {
+        version: 5,
+        description: "persist llm configurations",
+        sql: "
+ALTER TABLE agents ADD COLUMN llm_configuration TEXT;
+ALTER TABLE interactions ADD COLUMN llm_overrides TEXT;
+",
+    },
+    Migration {
+        version: 6,
+        description: "record token usage of interaction blocks",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN model_name TEXT;
+ALTER TABLE interaction_blocks ADD COLUMN prompt_tokens INTEGER;
+ALTER TABLE interaction_blocks ADD COLUMN completion_tokens INTEGER;
+ALTER TABLE interaction_blocks ADD COLUMN finish_reason TEXT;
+",
+    },
+    Migration {
+        version: 7,
+        description: "persist quotas",
+        sql: "
+ALTER TABLE agents ADD COLUMN quota TEXT;
+ALTER TABLE interactions ADD COLUMN quota TEXT;
+",
+    },
+    Migration {
+        version: 8,
+        description: "store embeddings of interaction blocks",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN embedding REAL[];
+",
+    },
+    Migration {
+        version: 9,
+        description: "persist prompt templates of interactions",
+        sql: "
+ALTER TABLE interactions ADD COLUMN prompt_template TEXT;
+",
+    },
+    Migration {
+        version: 10,
+        description: "scope meta to agents",
+        sql: "
+ALTER TABLE meta ADD COLUMN agent_id UUID REFERENCES agents(id);
+
+UPDATE meta SET agent_id = interactions.agent_id
+FROM interactions
+WHERE interactions.id = meta.default_interaction_id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS meta_agent_idx ON meta (agent_id);
+",
+    },
+    Migration {
+        version: 11,
+        description: "add participants",
+        sql: "
+CREATE TABLE IF NOT EXISTS participants (
+    id UUID PRIMARY KEY,
+
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+
+    display_name TEXT NOT NULL,
+    external_id TEXT UNIQUE,
+    metadata TEXT NOT NULL
+);
+
+ALTER TABLE interactions ADD COLUMN participant_id UUID REFERENCES participants(id);
+ALTER TABLE interaction_blocks ADD COLUMN participant_id UUID REFERENCES participants(id);
+
+CREATE INDEX IF NOT EXISTS interactions_participant_idx ON interactions (participant_id);
+CREATE INDEX IF NOT EXISTS interaction_blocks_participant_idx ON interaction_blocks (participant_id);
+",
+    },
+    Migration {
+        version: 12,
+        description: "add interaction members",
+        sql: "
+CREATE TABLE IF NOT EXISTS interaction_members (
+    id UUID PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL,
+
+    interaction_id UUID NOT NULL REFERENCES interactions(id),
+    participant_id UUID REFERENCES participants(id),
+    agent_id UUID REFERENCES agents(id),
+
+    CHECK ((participant_id IS NULL) <> (agent_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS interaction_members_participant_idx ON interaction_members (interaction_id, participant_id);
+CREATE UNIQUE INDEX IF NOT EXISTS interaction_members_agent_idx ON interaction_members (interaction_id, agent_id);
+",
+    },
+    Migration {
+        version: 13,
+        description: "record the agent that wrote each block",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN agent_id UUID REFERENCES agents(id);
+
+UPDATE interaction_blocks SET agent_id = interactions.agent_id
+FROM interactions
+WHERE interactions.id = interaction_blocks.interaction_id
+AND interaction_blocks.role IN ('agent', 'tool_call', 'tool');
+
+CREATE INDEX IF NOT EXISTS interaction_blocks_agent_idx ON interaction_blocks (agent_id);
+",
+    },
+    Migration {
+        version: 14,
+        description: "add summary usage",
+        sql: "
+CREATE TABLE IF NOT EXISTS summary_usage (
+    id UUID PRIMARY KEY,
+    created_at TIMESTAMP NOT NULL,
+
+    interaction_id UUID NOT NULL REFERENCES interactions(id),
+    agent_id UUID REFERENCES agents(id),
+
+    model_name TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    finish_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS summary_usage_interaction_idx ON summary_usage (interaction_id);
+CREATE INDEX IF NOT EXISTS summary_usage_agent_idx ON summary_usage (agent_id);
+",
+    },
+];
+
+#[cfg(feature = "sqlite")]
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: "
+CREATE TABLE IF NOT EXISTS agents (
+    id BLOB PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    name TEXT NOT NULL,
+
+    default_interaction_user_name TEXT NOT NULL,
+    default_interaction_constitution TEXT NOT NULL,
+    default_interaction_memory_size INTEGER NOT NULL
+);
+
+
+CREATE TABLE IF NOT EXISTS interactions (
+    id BLOB PRIMARY KEY,
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    user_name TEXT NOT NULL,
+    constitution TEXT NOT NULL,
+
+    short_term_memory TEXT NOT NULL,
+    default_long_term_memory_size INTEGER NOT NULL,
+
+    agent_id BLOB REFERENCES agents(id)
+);
+
+
+CREATE TABLE IF NOT EXISTS interaction_blocks (
+    id BLOB PRIMARY KEY,
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    interaction_id BLOB NOT NULL REFERENCES interactions(id),
+
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+
+    name TEXT
+);
+
+
+CREATE TABLE IF NOT EXISTS meta (
+    id BLOB PRIMARY KEY,
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    default_interaction_id BLOB NOT NULL REFERENCES interactions(id)
+);
+",
+    },
+    Migration {
+        version: 2,
+        description: "index interaction blocks by recency",
+        sql: "
+CREATE INDEX IF NOT EXISTS interaction_blocks_recency_idx
+    ON interaction_blocks (interaction_id, created_at DESC, id DESC);
+",
+    },
+    Migration {
+        version: 3,
+        description: "track summarized interaction history",
+        sql: "
+ALTER TABLE interactions ADD COLUMN summarized_until_created_at TEXT;
+ALTER TABLE interactions ADD COLUMN summarized_until_id BLOB;
+",
+    },
+    Migration {
+        version: 4,
+        description: "link tool calls to their results",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN tool_call_id TEXT;
+",
+    },
+    Migration {
+        version: 5,
+        description: "persist llm configurations",
+        sql: "
+ALTER TABLE agents ADD COLUMN llm_configuration TEXT;
+ALTER TABLE interactions ADD COLUMN llm_overrides TEXT;
+",
+    },
+    Migration {
+        version: 6,
+        description: "record token usage of interaction blocks",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN model_name TEXT;
+ALTER TABLE interaction_blocks ADD COLUMN prompt_tokens INTEGER;
+ALTER TABLE interaction_blocks ADD COLUMN completion_tokens INTEGER;
+ALTER TABLE interaction_blocks ADD COLUMN finish_reason TEXT;
+",
+    },
+    Migration {
+        version: 7,
+        description: "persist quotas",
+        sql: "
+ALTER TABLE agents ADD COLUMN quota TEXT;
+ALTER TABLE interactions ADD COLUMN quota TEXT;
+",
+    },
+    Migration {
+        version: 8,
+        description: "store embeddings of interaction blocks",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN embedding BLOB;
+",
+    },
+    Migration {
+        version: 9,
+        description: "persist prompt templates of interactions",
+        sql: "
+ALTER TABLE interactions ADD COLUMN prompt_template TEXT;
+",
+    },
+    Migration {
+        version: 10,
+        description: "scope meta to agents",
+        sql: "
+ALTER TABLE meta ADD COLUMN agent_id BLOB REFERENCES agents(id);
+
+UPDATE meta SET agent_id = (
+    SELECT agent_id FROM interactions WHERE interactions.id = meta.default_interaction_id
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS meta_agent_idx ON meta (agent_id);
+",
+    },
+    Migration {
+        version: 11,
+        description: "add participants",
+        sql: "
+CREATE TABLE IF NOT EXISTS participants (
+    id BLOB PRIMARY KEY,
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    display_name TEXT NOT NULL,
+    external_id TEXT UNIQUE,
+    metadata TEXT NOT NULL
+);
+
+ALTER TABLE interactions ADD COLUMN participant_id BLOB REFERENCES participants(id);
+ALTER TABLE interaction_blocks ADD COLUMN participant_id BLOB REFERENCES participants(id);
+
+CREATE INDEX IF NOT EXISTS interactions_participant_idx ON interactions (participant_id);
+CREATE INDEX IF NOT EXISTS interaction_blocks_participant_idx ON interaction_blocks (participant_id);
+",
+    },
+    Migration {
+        version: 12,
+        description: "add interaction members",
+        sql: "
+CREATE TABLE IF NOT EXISTS interaction_members (
+    id BLOB PRIMARY KEY,
+    created_at TEXT NOT NULL,
+
+    interaction_id BLOB NOT NULL REFERENCES interactions(id),
+    participant_id BLOB REFERENCES participants(id),
+    agent_id BLOB REFERENCES agents(id),
+
+    CHECK ((participant_id IS NULL) <> (agent_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS interaction_members_participant_idx ON interaction_members (interaction_id, participant_id);
+CREATE UNIQUE INDEX IF NOT EXISTS interaction_members_agent_idx ON interaction_members (interaction_id, agent_id);
+",
+    },
+    Migration {
+        version: 13,
+        description: "record the agent that wrote each block",
+        sql: "
+ALTER TABLE interaction_blocks ADD COLUMN agent_id BLOB REFERENCES agents(id);
+
+UPDATE interaction_blocks SET agent_id = (
+    SELECT agent_id FROM interactions WHERE interactions.id = interaction_blocks.interaction_id
+)
+WHERE role IN ('agent', 'tool_call', 'tool');
+
+CREATE INDEX IF NOT EXISTS interaction_blocks_agent_idx ON interaction_blocks (agent_id);
+",
+    },
+    Migration {
+        version: 14,
+        description: "add summary usage",
+        sql: "
+CREATE TABLE IF NOT EXISTS summary_usage (
+    id BLOB PRIMARY KEY,
+    created_at TEXT NOT NULL,
+
+    interaction_id BLOB NOT NULL REFERENCES interactions(id),
+    agent_id BLOB REFERENCES agents(id),
+
+    model_name TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    finish_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS summary_usage_interaction_idx ON summary_usage (interaction_id);
+CREATE INDEX IF NOT EXISTS summary_usage_agent_idx ON summary_usage (agent_id);
+",
+    },
+];
+
+const POSTGRES_MIGRATIONS_LOCK: i64 = 0x6d79_7365_6c66;
+
+const CREATE_POSTGRES_MIGRATIONS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version BIGINT PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at TIMESTAMP NOT NULL
+);
+";
+
+pub fn latest_version(migrations: &[Migration]) -> i64 {
+    migrations
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+pub async fn migrate_postgres(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    query("SELECT pg_advisory_xact_lock($1)")
+        .bind(POSTGRES_MIGRATIONS_LOCK)
+        .execute(&mut tx)
+        .await?;
+
+    tx.execute(CREATE_POSTGRES_MIGRATIONS_TABLE_SQL).await?;
+
+    let current_version =
+        query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(&mut tx)
+            .await?
+            .unwrap_or(0);
+
+    let mut applied = Vec::new();
+
+    for migration in POSTGRES_MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+    {
+        tx.execute(migration.sql).await?;
+
+        query(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now().naive_utc())
+        .execute(&mut tx)
+        .await?;
+
+        applied.push(migration.version);
+    }
+
+    tx.commit().await?;
+
+    Ok(applied)
+}
+
+pub async fn postgres_schema_version(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let exists = query_scalar::<_, Option<String>>("SELECT to_regclass('schema_migrations')::TEXT")
+        .fetch_one(pool)
+        .await?
+        .is_some();
+
+    if !exists {
+        return Ok(0);
+    }
+
+    Ok(
+        query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(pool)
+            .await?
+            .unwrap_or(0),
+    )
+}
+
+#[cfg(feature = "sqlite")]
+const CREATE_SQLITE_MIGRATIONS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at TEXT NOT NULL
+);
+";
+
+#[cfg(feature = "sqlite")]
+pub async fn migrate_sqlite(pool: &sqlx::sqlite::SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    tx.execute(CREATE_SQLITE_MIGRATIONS_TABLE_SQL).await?;
+
+    let current_version =
+        query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(&mut tx)
+            .await?
+            .unwrap_or(0);
+
+    let mut applied = Vec::new();
+
+    for migration in SQLITE_MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+    {
+        tx.execute(migration.sql).await?;
+
+        query(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.description)
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await?;
+
+        applied.push(migration.version);
+    }
+
+    tx.commit().await?;
+
+    Ok(applied)
+}
+
+#[cfg(feature = "sqlite")]
+pub async fn sqlite_schema_version(pool: &sqlx::sqlite::SqlitePool) -> Result<i64, sqlx::Error> {
+    let exists = query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'",
+    )
+    .fetch_one(pool)
+    .await?
+        > 0;
+
+    if !exists {
+        return Ok(0);
+    }
+
+    Ok(
+        query_scalar::<_, Option<i64>>("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(pool)
+            .await?
+            .unwrap_or(0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_consecutive() {
+        for (index, migration) in POSTGRES_MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1);
+        }
+    }
+
+    // `schema_version` has to mean the same schema on every backend.
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn backends_share_versions() {
+        let versions = |migrations: &[Migration]| {
+            migrations
+                .iter()
+                .map(|migration| (migration.version, migration.description))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(versions(POSTGRES_MIGRATIONS), versions(SQLITE_MIGRATIONS));
+    }
+}
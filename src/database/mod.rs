@@ -1,3 +1,8 @@
 pub mod engine;
+pub mod in_memory;
 pub mod memory;
-pub mod models;
+pub mod migrations;
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod store;
@@ -0,0 +1,1311 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{postgres::PgPool, query, query_as, Executor, FromRow};
+use uuid::Uuid;
+
+use crate::{
+    error::MyselfError,
+    llm::LLMOverrides,
+    sdk::{
+        group::{InteractionMember, Member},
+        interaction::{
+            HistoryCursor, InteractionBlock, InteractionBlockRole, InteractionsQuery, Meta,
+        },
+        participant::Participant,
+        quota::Quota,
+        template::PromptTemplate,
+        usage::{ModelUsage, TokenUsage},
+    },
+};
+
+use super::{
+    engine::new_postgres_pool,
+    migrations::{migrate_postgres, postgres_schema_version},
+    store::{
+        llm_configuration_from_column, llm_overrides_from_column, member_from_columns,
+        metadata_from_column, most_similar, prompt_template_from_column, quota_from_column,
+        token_usage_from_columns, AgentRecord, InteractionRecord, MemoryStore,
+    },
+};
+
+// Timestamps are stored as UTC without a time zone.
+
+struct AgentRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    name: String,
+    default_interaction_user_name: String,
+    default_interaction_constitution: String,
+    default_interaction_memory_size: i32,
+    llm_configuration: Option<String>,
+    quota: Option<String>,
+}
+
+impl TryFrom<AgentRow> for AgentRecord {
+    type Error = MyselfError;
+
+    fn try_from(row: AgentRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            updated_at: row.updated_at.and_utc(),
+            name: row.name,
+            default_user_name: row.default_interaction_user_name,
+            default_constitution: row.default_interaction_constitution,
+            default_memory_size: row.default_interaction_memory_size as usize,
+            llm_configuration: llm_configuration_from_column(row.llm_configuration)?,
+            quota: quota_from_column(row.quota)?,
+        })
+    }
+}
+
+struct InteractionRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    user_name: String,
+    default_long_term_memory_size: i32,
+    constitution: String,
+    short_term_memory: String,
+    agent_id: Option<Uuid>,
+    summarized_until_created_at: Option<NaiveDateTime>,
+    summarized_until_id: Option<Uuid>,
+    llm_overrides: Option<String>,
+    quota: Option<String>,
+    prompt_template: Option<String>,
+    participant_id: Option<Uuid>,
+}
+
+impl TryFrom<InteractionRow> for InteractionRecord {
+    type Error = MyselfError;
+
+    fn try_from(row: InteractionRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            updated_at: row.updated_at.and_utc(),
+            user_name: row.user_name,
+            constitution: row.constitution,
+            short_term_memory: row.short_term_memory,
+            long_term_memory_size: row.default_long_term_memory_size as usize,
+            llm_overrides: llm_overrides_from_column(row.llm_overrides)?,
+            quota: quota_from_column(row.quota)?,
+            prompt_template: prompt_template_from_column(row.prompt_template)?,
+            agent_id: row.agent_id,
+            participant_id: row.participant_id,
+            summarized_until: row
+                .summarized_until_created_at
+                .zip(row.summarized_until_id)
+                .map(|(created_at, id)| HistoryCursor {
+                    created_at: created_at.and_utc(),
+                    id,
+                }),
+        })
+    }
+}
+
+// Also read at runtime by the embedding searches, whose rows add a column.
+#[derive(FromRow)]
+struct InteractionBlockRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    interaction_id: Uuid,
+    role: String,
+    content: String,
+    name: Option<String>,
+    tool_call_id: Option<String>,
+    model_name: Option<String>,
+    prompt_tokens: Option<i32>,
+    completion_tokens: Option<i32>,
+    finish_reason: Option<String>,
+    participant_id: Option<Uuid>,
+    agent_id: Option<Uuid>,
+}
+
+impl TryFrom<InteractionBlockRow> for InteractionBlock {
+    type Error = MyselfError;
+
+    fn try_from(row: InteractionBlockRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            updated_at: row.updated_at.and_utc(),
+            interaction_id: row.interaction_id,
+            role: InteractionBlockRole::from_str(&row.role)?,
+            content: row.content,
+            name: row.name,
+            tool_call_id: row.tool_call_id,
+            participant_id: row.participant_id,
+            agent_id: row.agent_id,
+            usage: token_usage_from_columns(
+                row.model_name,
+                row.prompt_tokens.map(i64::from),
+                row.completion_tokens.map(i64::from),
+                row.finish_reason,
+            ),
+            embedding: None,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct EmbeddedBlockRow {
+    #[sqlx(flatten)]
+    block: InteractionBlockRow,
+    embedding: Vec<f32>,
+}
+
+#[derive(FromRow)]
+struct SimilarBlockRow {
+    #[sqlx(flatten)]
+    block: InteractionBlockRow,
+    similarity: f64,
+}
+
+struct ModelUsageRow {
+    model_name: String,
+    responses: i64,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+}
+
+impl From<ModelUsageRow> for ModelUsage {
+    fn from(row: ModelUsageRow) -> Self {
+        Self {
+            model_name: row.model_name,
+            responses: row.responses as usize,
+            prompt_tokens: row.prompt_tokens as usize,
+            completion_tokens: row.completion_tokens as usize,
+        }
+    }
+}
+
+struct MetaRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    agent_id: Uuid,
+    default_interaction_id: Uuid,
+}
+
+impl From<MetaRow> for Meta {
+    fn from(row: MetaRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            updated_at: row.updated_at.and_utc(),
+            agent_id: row.agent_id,
+            default_interaction_id: row.default_interaction_id,
+        }
+    }
+}
+
+struct ParticipantRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    display_name: String,
+    external_id: Option<String>,
+    metadata: String,
+}
+
+impl TryFrom<ParticipantRow> for Participant {
+    type Error = MyselfError;
+
+    fn try_from(row: ParticipantRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            updated_at: row.updated_at.and_utc(),
+            display_name: row.display_name,
+            external_id: row.external_id,
+            metadata: metadata_from_column(row.metadata)?,
+        })
+    }
+}
+
+struct MemberRow {
+    id: Uuid,
+    created_at: NaiveDateTime,
+    interaction_id: Uuid,
+    participant_id: Option<Uuid>,
+    agent_id: Option<Uuid>,
+}
+
+impl TryFrom<MemberRow> for InteractionMember {
+    type Error = MyselfError;
+
+    fn try_from(row: MemberRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at.and_utc(),
+            interaction_id: row.interaction_id,
+            member: member_from_columns(row.participant_id, row.agent_id)?,
+        })
+    }
+}
+
+// Embeddings are stored as `REAL[]`. When the pgvector extension can be used,
+// similarity is computed by the database; otherwise the blocks are compared
+// one by one, as in the other stores.
+#[derive(Debug, Clone)]
+pub struct PostgresStore {
+    pool: PgPool,
+    pgvector: bool,
+}
+
+impl PostgresStore {
+    pub async fn new(database_url: String) -> Result<Self, MyselfError> {
+        let pool = new_postgres_pool(database_url).await?;
+
+        migrate_postgres(&pool).await?;
+
+        let pgvector = enable_pgvector(&pool).await?;
+
+        Ok(Self { pool, pgvector })
+    }
+
+    pub async fn migrate(&self) -> Result<Vec<i64>, MyselfError> {
+        Ok(migrate_postgres(&self.pool).await?)
+    }
+
+    // A store on an already migrated database, searching embeddings without
+    // pgvector.
+    pub fn with_pool(pool: PgPool) -> Self {
+        Self {
+            pool,
+            pgvector: false,
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn uses_pgvector(&self) -> bool {
+        self.pgvector
+    }
+}
+
+// Installs pgvector when the server offers it, returning whether it can be
+// used. Servers without it, or roles that may not create extensions, fall
+// back to comparing embeddings in the store.
+async fn enable_pgvector(pool: &PgPool) -> Result<bool, MyselfError> {
+    let installed = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'vector')",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if installed {
+        return Ok(true);
+    }
+
+    let available = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector')",
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(available
+        && pool
+            .execute("CREATE EXTENSION IF NOT EXISTS vector")
+            .await
+            .is_ok())
+}
+
+// `query_as!` cannot fill the flattened block rows of the embedding searches,
+// and the `vector` type only exists where pgvector is installed, so both
+// searches are checked at runtime.
+impl PostgresStore {
+    async fn search_blocks_with_pgvector(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        query_as::<_, SimilarBlockRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, interaction_id, role, content, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id, 1 - (embedding::vector <=> $2::vector) AS similarity
+            FROM interaction_blocks
+            WHERE interaction_id = $1 AND embedding IS NOT NULL
+                AND cardinality(embedding) = $3
+                AND ($4::TIMESTAMP IS NULL OR (created_at, id) < ($4, $5))
+            ORDER BY embedding::vector <=> $2::vector
+            LIMIT $6
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(embedding)
+        .bind(embedding.len() as i32)
+        .bind(before.map(|cursor| cursor.created_at.naive_utc()))
+        .bind(before.map(|cursor| cursor.id))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok((row.block.try_into()?, row.similarity as f32)))
+        .collect()
+    }
+
+    async fn search_blocks_in_store(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        let candidates = query_as::<_, EmbeddedBlockRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, interaction_id, role, content, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id, embedding
+            FROM interaction_blocks
+            WHERE interaction_id = $1 AND embedding IS NOT NULL
+                AND cardinality(embedding) = $2
+                AND ($3::TIMESTAMP IS NULL OR (created_at, id) < ($3, $4))
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(embedding.len() as i32)
+        .bind(before.map(|cursor| cursor.created_at.naive_utc()))
+        .bind(before.map(|cursor| cursor.id))
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| Ok((row.block.try_into()?, row.embedding)))
+        .collect::<Result<Vec<_>, MyselfError>>()?;
+
+        Ok(most_similar(candidates.into_iter(), embedding, limit))
+    }
+}
+
+#[async_trait]
+impl MemoryStore for PostgresStore {
+    async fn schema_version(&self) -> Result<Option<i64>, MyselfError> {
+        Ok(Some(postgres_schema_version(&self.pool).await?))
+    }
+
+    async fn insert_agent(&self, agent: AgentRecord) -> Result<AgentRecord, MyselfError> {
+        query_as!(
+            AgentRow,
+            r#"
+            INSERT INTO agents (id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            "#,
+            agent.id,
+            agent.created_at.naive_utc(),
+            agent.updated_at.naive_utc(),
+            agent.name,
+            agent.default_user_name,
+            agent.default_constitution,
+            agent.default_memory_size as i32,
+            agent
+                .llm_configuration
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            serde_json::to_string(&agent.quota)?,
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .try_into()
+    }
+
+    async fn get_agent(&self, id: Uuid) -> Result<Option<AgentRecord>, MyselfError> {
+        query_as!(
+            AgentRow,
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(AgentRecord::try_from)
+        .transpose()
+    }
+
+    async fn get_agent_by_name(&self, name: &str) -> Result<Option<AgentRecord>, MyselfError> {
+        query_as!(
+            AgentRow,
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            WHERE name = $1
+            ORDER BY created_at ASC, id ASC
+            LIMIT 1
+            "#,
+            name,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(AgentRecord::try_from)
+        .transpose()
+    }
+
+    async fn list_agents(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<AgentRecord>, MyselfError> {
+        query_as!(
+            AgentRow,
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            ORDER BY created_at ASC, id ASC
+            LIMIT $1 OFFSET $2
+            "#,
+            limit as i64,
+            offset as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(AgentRecord::try_from)
+        .collect()
+    }
+
+    async fn update_agent(&self, agent: AgentRecord) -> Result<Option<AgentRecord>, MyselfError> {
+        query_as!(
+            AgentRow,
+            r#"
+            UPDATE agents
+            SET updated_at = $2, name = $3, default_interaction_user_name = $4, default_interaction_constitution = $5, default_interaction_memory_size = $6, llm_configuration = $7, quota = $8
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            "#,
+            agent.id,
+            agent.updated_at.naive_utc(),
+            agent.name,
+            agent.default_user_name,
+            agent.default_constitution,
+            agent.default_memory_size as i32,
+            agent
+                .llm_configuration
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            serde_json::to_string(&agent.quota)?,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(AgentRecord::try_from)
+        .transpose()
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, MyselfError> {
+        let mut tx = self.pool.begin().await?;
+
+        query!(
+            r#"
+            DELETE FROM meta
+            WHERE agent_id = $1 OR default_interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM interaction_members
+            WHERE agent_id = $1 OR interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM interaction_blocks
+            WHERE interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM summary_usage
+            WHERE interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        // Keeps what the agent wrote in other agents' group interactions.
+        query!(
+            r#"
+            UPDATE interaction_blocks SET agent_id = NULL
+            WHERE agent_id = $1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            r#"
+            UPDATE summary_usage SET agent_id = NULL
+            WHERE agent_id = $1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM interactions
+            WHERE agent_id = $1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let deleted = query!(
+            r#"
+            DELETE FROM agents
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn insert_interaction(
+        &self,
+        interaction: InteractionRecord,
+    ) -> Result<InteractionRecord, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            INSERT INTO interactions (id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            interaction.id,
+            interaction.created_at.naive_utc(),
+            interaction.updated_at.naive_utc(),
+            interaction.user_name,
+            interaction.long_term_memory_size as i32,
+            interaction.constitution,
+            interaction.short_term_memory,
+            interaction.agent_id,
+            interaction
+                .summarized_until
+                .map(|cursor| cursor.created_at.naive_utc()),
+            interaction.summarized_until.map(|cursor| cursor.id),
+            serde_json::to_string(&interaction.llm_overrides)?,
+            serde_json::to_string(&interaction.quota)?,
+            interaction
+                .prompt_template
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+            interaction.participant_id,
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .try_into()
+    }
+
+    async fn get_interaction(&self, id: Uuid) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn get_agent_interactions(
+        &self,
+        agent_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE agent_id = $1
+            ORDER BY
+                CASE WHEN $2 = 'created_at_asc' THEN created_at END ASC,
+                CASE WHEN $2 = 'created_at_desc' THEN created_at END DESC,
+                CASE WHEN $2 = 'updated_at_asc' THEN updated_at END ASC,
+                CASE WHEN $2 = 'updated_at_desc' THEN updated_at END DESC,
+                id
+            LIMIT $3
+            OFFSET $4
+            "#,
+            agent_id,
+            options.order.as_str(),
+            options.limit as i64,
+            options.offset as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionRecord::try_from)
+        .collect()
+    }
+
+    async fn update_constitution(
+        &self,
+        id: Uuid,
+        constitution: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET constitution = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            constitution,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn set_short_term_memory(
+        &self,
+        id: Uuid,
+        memory: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET short_term_memory = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            memory,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn set_llm_overrides(
+        &self,
+        id: Uuid,
+        overrides: LLMOverrides,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET llm_overrides = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            serde_json::to_string(&overrides)?,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn set_quota(
+        &self,
+        id: Uuid,
+        quota: Quota,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET quota = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            serde_json::to_string(&quota)?,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn set_prompt_template(
+        &self,
+        id: Uuid,
+        template: Option<PromptTemplate>,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET prompt_template = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            template.as_ref().map(serde_json::to_string).transpose()?,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn set_summary(
+        &self,
+        id: Uuid,
+        summary: String,
+        summarized_until: HistoryCursor,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            UPDATE interactions
+            SET short_term_memory = $2, summarized_until_created_at = $3, summarized_until_id = $4, updated_at = $5
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+            id,
+            summary,
+            summarized_until.created_at.naive_utc(),
+            summarized_until.id,
+            updated_at.naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(InteractionRecord::try_from)
+        .transpose()
+    }
+
+    async fn append_block(
+        &self,
+        interaction_id: Uuid,
+        block: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        let row = query_as!(
+            InteractionBlockRow,
+            r#"
+            INSERT INTO interaction_blocks (id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id, embedding)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) RETURNING id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            "#,
+            block.id,
+            block.created_at.naive_utc(),
+            block.updated_at.naive_utc(),
+            interaction_id,
+            block.role.as_str(),
+            block.content,
+            block.name,
+            block.tool_call_id,
+            block.usage.as_ref().map(|usage| usage.model_name.clone()),
+            block.usage.as_ref().map(|usage| usage.prompt_tokens as i32),
+            block.usage.as_ref().map(|usage| usage.completion_tokens as i32),
+            block
+                .usage
+                .as_ref()
+                .and_then(|usage| usage.finish_reason.clone()),
+            block.participant_id,
+            block.agent_id,
+            block.embedding.as_deref(),
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        query!(
+            r#"
+            UPDATE interactions
+            SET updated_at = $2
+            WHERE id = $1
+            "#,
+            interaction_id,
+            row.created_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(InteractionBlock {
+            embedding: block.embedding.clone(),
+            ..row.try_into()?
+        })
+    }
+
+    async fn get_long_term_memory(
+        &self,
+        interaction_id: Uuid,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        let mut blocks = query_as!(
+            InteractionBlockRow,
+            r#"
+            SELECT id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            FROM interaction_blocks
+            WHERE interaction_id = $1
+                AND ($2::TIMESTAMP IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#,
+            interaction_id,
+            before.map(|cursor| cursor.created_at.naive_utc()),
+            before.map(|cursor| cursor.id),
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionBlock::try_from)
+        .collect::<Result<Vec<_>, MyselfError>>()?;
+
+        blocks.reverse();
+
+        Ok(blocks)
+    }
+
+    async fn get_blocks_after(
+        &self,
+        interaction_id: Uuid,
+        after: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        query_as!(
+            InteractionBlockRow,
+            r#"
+            SELECT id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            FROM interaction_blocks
+            WHERE interaction_id = $1
+                AND ($2::TIMESTAMP IS NULL OR (created_at, id) > ($2, $3))
+            ORDER BY created_at ASC, id ASC
+            LIMIT $4
+            "#,
+            interaction_id,
+            after.map(|cursor| cursor.created_at.naive_utc()),
+            after.map(|cursor| cursor.id),
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionBlock::try_from)
+        .collect()
+    }
+
+    async fn append_summary_usage(
+        &self,
+        interaction_id: Uuid,
+        agent_id: Option<Uuid>,
+        usage: &TokenUsage,
+        created_at: DateTime<Utc>,
+    ) -> Result<(), MyselfError> {
+        query!(
+            r#"
+            INSERT INTO summary_usage (id, created_at, interaction_id, agent_id, model_name, prompt_tokens, completion_tokens, finish_reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            Uuid::new_v4(),
+            created_at.naive_utc(),
+            interaction_id,
+            agent_id,
+            usage.model_name,
+            usage.prompt_tokens as i32,
+            usage.completion_tokens as i32,
+            usage.finish_reason,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_interaction_usage(
+        &self,
+        interaction_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        Ok(query_as!(
+            ModelUsageRow,
+            r#"
+            SELECT model_name AS "model_name!", COUNT(*) AS "responses!", SUM(prompt_tokens) AS "prompt_tokens!", SUM(completion_tokens) AS "completion_tokens!"
+            FROM (
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM interaction_blocks
+                WHERE interaction_id = $1 AND model_name IS NOT NULL
+                    AND ($2::TIMESTAMP IS NULL OR created_at >= $2)
+                UNION ALL
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM summary_usage
+                WHERE interaction_id = $1
+                    AND ($2::TIMESTAMP IS NULL OR created_at >= $2)
+            ) calls
+            GROUP BY model_name
+            ORDER BY model_name
+            "#,
+            interaction_id,
+            since.map(|since| since.naive_utc()),
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(ModelUsage::from)
+        .collect())
+    }
+
+    async fn get_agent_usage(
+        &self,
+        agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        Ok(query_as!(
+            ModelUsageRow,
+            r#"
+            SELECT model_name AS "model_name!", COUNT(*) AS "responses!", SUM(prompt_tokens) AS "prompt_tokens!", SUM(completion_tokens) AS "completion_tokens!"
+            FROM (
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM interaction_blocks
+                WHERE agent_id = $1 AND model_name IS NOT NULL
+                    AND ($2::TIMESTAMP IS NULL OR created_at >= $2)
+                UNION ALL
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM summary_usage
+                WHERE agent_id = $1
+                    AND ($2::TIMESTAMP IS NULL OR created_at >= $2)
+            ) calls
+            GROUP BY model_name
+            ORDER BY model_name
+            "#,
+            agent_id,
+            since.map(|since| since.naive_utc()),
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(ModelUsage::from)
+        .collect())
+    }
+
+    async fn search_blocks(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        if self.pgvector {
+            self.search_blocks_with_pgvector(interaction_id, embedding, before, limit)
+                .await
+        } else {
+            self.search_blocks_in_store(interaction_id, embedding, before, limit)
+                .await
+        }
+    }
+
+    async fn flush_long_term_memory(&self, interaction_id: Uuid) -> Result<(), MyselfError> {
+        query!(
+            r#"
+            DELETE FROM interaction_blocks
+            WHERE interaction_id = $1
+            "#,
+            interaction_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_meta(&self, agent_id: Uuid) -> Result<Option<Meta>, MyselfError> {
+        Ok(query_as!(
+            MetaRow,
+            r#"
+            SELECT id, created_at, updated_at, agent_id AS "agent_id!", default_interaction_id
+            FROM meta
+            WHERE agent_id = $1
+            "#,
+            agent_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Meta::from))
+    }
+
+    async fn insert_meta(&self, meta: Meta) -> Result<Meta, MyselfError> {
+        Ok(query_as!(
+            MetaRow,
+            r#"
+            INSERT INTO meta (id, created_at, updated_at, agent_id, default_interaction_id)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at, updated_at, agent_id AS "agent_id!", default_interaction_id
+            "#,
+            meta.id,
+            meta.created_at.naive_utc(),
+            meta.updated_at.naive_utc(),
+            meta.agent_id,
+            meta.default_interaction_id,
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .into())
+    }
+
+    async fn set_default_interaction(
+        &self,
+        meta_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<Option<Meta>, MyselfError> {
+        Ok(query_as!(
+            MetaRow,
+            r#"
+            UPDATE meta
+            SET default_interaction_id = $1, updated_at = $3
+            WHERE id = $2
+            RETURNING id, created_at, updated_at, agent_id AS "agent_id!", default_interaction_id
+            "#,
+            interaction_id,
+            meta_id,
+            Utc::now().naive_utc(),
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Meta::from))
+    }
+
+    async fn insert_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError> {
+        query_as!(
+            ParticipantRow,
+            r#"
+            INSERT INTO participants (id, created_at, updated_at, display_name, external_id, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, created_at, updated_at, display_name, external_id, metadata
+            "#,
+            participant.id,
+            participant.created_at.naive_utc(),
+            participant.updated_at.naive_utc(),
+            participant.display_name,
+            participant.external_id,
+            serde_json::to_string(&participant.metadata)?,
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .try_into()
+    }
+
+    async fn get_participant(&self, id: Uuid) -> Result<Option<Participant>, MyselfError> {
+        query_as!(
+            ParticipantRow,
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Participant::try_from)
+        .transpose()
+    }
+
+    async fn get_participant_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Result<Option<Participant>, MyselfError> {
+        query_as!(
+            ParticipantRow,
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            WHERE external_id = $1
+            "#,
+            external_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Participant::try_from)
+        .transpose()
+    }
+
+    async fn list_participants(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Participant>, MyselfError> {
+        query_as!(
+            ParticipantRow,
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            ORDER BY created_at ASC, id ASC
+            LIMIT $1 OFFSET $2
+            "#,
+            limit as i64,
+            offset as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Participant::try_from)
+        .collect()
+    }
+
+    async fn update_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Option<Participant>, MyselfError> {
+        query_as!(
+            ParticipantRow,
+            r#"
+            UPDATE participants
+            SET updated_at = $2, display_name = $3, external_id = $4, metadata = $5
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, display_name, external_id, metadata
+            "#,
+            participant.id,
+            participant.updated_at.naive_utc(),
+            participant.display_name,
+            participant.external_id,
+            serde_json::to_string(&participant.metadata)?,
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .map(Participant::try_from)
+        .transpose()
+    }
+
+    async fn get_participant_interactions(
+        &self,
+        participant_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        query_as!(
+            InteractionRow,
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE participant_id = $1 OR id IN (SELECT interaction_id FROM interaction_blocks WHERE participant_id = $1)
+            ORDER BY
+                CASE WHEN $2 = 'created_at_asc' THEN created_at END ASC,
+                CASE WHEN $2 = 'created_at_desc' THEN created_at END DESC,
+                CASE WHEN $2 = 'updated_at_asc' THEN updated_at END ASC,
+                CASE WHEN $2 = 'updated_at_desc' THEN updated_at END DESC,
+                id
+            LIMIT $3
+            OFFSET $4
+            "#,
+            participant_id,
+            options.order.as_str(),
+            options.limit as i64,
+            options.offset as i64,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionRecord::try_from)
+        .collect()
+    }
+
+    async fn insert_member(
+        &self,
+        member: InteractionMember,
+    ) -> Result<InteractionMember, MyselfError> {
+        query_as!(
+            MemberRow,
+            r#"
+            INSERT INTO interaction_members (id, created_at, interaction_id, participant_id, agent_id)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at, interaction_id, participant_id, agent_id
+            "#,
+            member.id,
+            member.created_at.naive_utc(),
+            member.interaction_id,
+            member.member.participant_id(),
+            member.member.agent_id(),
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .try_into()
+    }
+
+    async fn get_members(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<Vec<InteractionMember>, MyselfError> {
+        query_as!(
+            MemberRow,
+            r#"
+            SELECT id, created_at, interaction_id, participant_id, agent_id
+            FROM interaction_members
+            WHERE interaction_id = $1
+            ORDER BY created_at ASC, id ASC
+            "#,
+            interaction_id,
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionMember::try_from)
+        .collect()
+    }
+
+    async fn delete_member(
+        &self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<bool, MyselfError> {
+        let deleted = query!(
+            r#"
+            DELETE FROM interaction_members
+            WHERE interaction_id = $1 AND (participant_id = $2 OR agent_id = $3)
+            "#,
+            interaction_id,
+            member.participant_id(),
+            member.agent_id(),
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(deleted > 0)
+    }
+}
@@ -0,0 +1,1212 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{query, query_as, sqlite::SqlitePool, FromRow};
+use uuid::Uuid;
+
+use crate::{
+    error::MyselfError,
+    llm::LLMOverrides,
+    sdk::{
+        group::{InteractionMember, Member},
+        interaction::{
+            HistoryCursor, InteractionBlock, InteractionBlockRole, InteractionsOrder,
+            InteractionsQuery, Meta,
+        },
+        participant::Participant,
+        quota::Quota,
+        template::PromptTemplate,
+        usage::{ModelUsage, TokenUsage},
+    },
+};
+
+use super::{
+    engine::new_sqlite_pool,
+    migrations::{migrate_sqlite, sqlite_schema_version},
+    store::{
+        llm_configuration_from_column, llm_overrides_from_column, member_from_columns,
+        metadata_from_column, most_similar, prompt_template_from_column, quota_from_column,
+        token_usage_from_columns, AgentRecord, InteractionRecord, MemoryStore,
+    },
+};
+
+// The offline query data in `sqlx-data.json` only describes the Postgres
+// schema, so the SQLite store uses runtime-checked queries instead of `query!`.
+
+// Embeddings are stored as little-endian `f32`s.
+fn embedding_to_bytes(embedding: &[f32]) -> Vec<u8> {
+    embedding
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn embedding_from_bytes(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+#[derive(FromRow)]
+struct AgentRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    name: String,
+    default_interaction_user_name: String,
+    default_interaction_constitution: String,
+    default_interaction_memory_size: i64,
+    llm_configuration: Option<String>,
+    quota: Option<String>,
+}
+
+impl TryFrom<AgentRow> for AgentRecord {
+    type Error = MyselfError;
+
+    fn try_from(row: AgentRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            name: row.name,
+            default_user_name: row.default_interaction_user_name,
+            default_constitution: row.default_interaction_constitution,
+            default_memory_size: row.default_interaction_memory_size as usize,
+            llm_configuration: llm_configuration_from_column(row.llm_configuration)?,
+            quota: quota_from_column(row.quota)?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct InteractionRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    user_name: String,
+    constitution: String,
+    short_term_memory: String,
+    default_long_term_memory_size: i64,
+    agent_id: Option<Uuid>,
+    summarized_until_created_at: Option<DateTime<Utc>>,
+    summarized_until_id: Option<Uuid>,
+    llm_overrides: Option<String>,
+    quota: Option<String>,
+    prompt_template: Option<String>,
+    participant_id: Option<Uuid>,
+}
+
+impl TryFrom<InteractionRow> for InteractionRecord {
+    type Error = MyselfError;
+
+    fn try_from(row: InteractionRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            user_name: row.user_name,
+            constitution: row.constitution,
+            short_term_memory: row.short_term_memory,
+            long_term_memory_size: row.default_long_term_memory_size as usize,
+            llm_overrides: llm_overrides_from_column(row.llm_overrides)?,
+            quota: quota_from_column(row.quota)?,
+            prompt_template: prompt_template_from_column(row.prompt_template)?,
+            agent_id: row.agent_id,
+            participant_id: row.participant_id,
+            summarized_until: row
+                .summarized_until_created_at
+                .zip(row.summarized_until_id)
+                .map(|(created_at, id)| HistoryCursor { created_at, id }),
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct InteractionBlockRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    interaction_id: Uuid,
+    role: String,
+    content: String,
+    name: Option<String>,
+    tool_call_id: Option<String>,
+    model_name: Option<String>,
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+    finish_reason: Option<String>,
+    participant_id: Option<Uuid>,
+    agent_id: Option<Uuid>,
+}
+
+#[derive(FromRow)]
+struct EmbeddedBlockRow {
+    #[sqlx(flatten)]
+    block: InteractionBlockRow,
+    embedding: Vec<u8>,
+}
+
+impl TryFrom<InteractionBlockRow> for InteractionBlock {
+    type Error = MyselfError;
+
+    fn try_from(row: InteractionBlockRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            interaction_id: row.interaction_id,
+            role: InteractionBlockRole::from_str(&row.role)?,
+            content: row.content,
+            name: row.name,
+            tool_call_id: row.tool_call_id,
+            participant_id: row.participant_id,
+            agent_id: row.agent_id,
+            usage: token_usage_from_columns(
+                row.model_name,
+                row.prompt_tokens,
+                row.completion_tokens,
+                row.finish_reason,
+            ),
+            embedding: None,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct ModelUsageRow {
+    model_name: String,
+    responses: i64,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+}
+
+impl From<ModelUsageRow> for ModelUsage {
+    fn from(row: ModelUsageRow) -> Self {
+        Self {
+            model_name: row.model_name,
+            responses: row.responses as usize,
+            prompt_tokens: row.prompt_tokens as usize,
+            completion_tokens: row.completion_tokens as usize,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct MetaRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    agent_id: Uuid,
+    default_interaction_id: Uuid,
+}
+
+impl From<MetaRow> for Meta {
+    fn from(row: MetaRow) -> Self {
+        Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            agent_id: row.agent_id,
+            default_interaction_id: row.default_interaction_id,
+        }
+    }
+}
+
+#[derive(FromRow)]
+struct ParticipantRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    display_name: String,
+    external_id: Option<String>,
+    metadata: String,
+}
+
+impl TryFrom<ParticipantRow> for Participant {
+    type Error = MyselfError;
+
+    fn try_from(row: ParticipantRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            display_name: row.display_name,
+            external_id: row.external_id,
+            metadata: metadata_from_column(row.metadata)?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+struct MemberRow {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    interaction_id: Uuid,
+    participant_id: Option<Uuid>,
+    agent_id: Option<Uuid>,
+}
+
+impl TryFrom<MemberRow> for InteractionMember {
+    type Error = MyselfError;
+
+    fn try_from(row: MemberRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            interaction_id: row.interaction_id,
+            member: member_from_columns(row.participant_id, row.agent_id)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn new(database_url: String) -> Result<Self, MyselfError> {
+        let pool = new_sqlite_pool(database_url).await?;
+
+        migrate_sqlite(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn migrate(&self) -> Result<Vec<i64>, MyselfError> {
+        Ok(migrate_sqlite(&self.pool).await?)
+    }
+
+    pub fn with_pool(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl MemoryStore for SqliteStore {
+    async fn schema_version(&self) -> Result<Option<i64>, MyselfError> {
+        Ok(Some(sqlite_schema_version(&self.pool).await?))
+    }
+
+    async fn insert_agent(&self, agent: AgentRecord) -> Result<AgentRecord, MyselfError> {
+        let row = query_as::<_, AgentRow>(
+            r#"
+            INSERT INTO agents (id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            "#,
+        )
+        .bind(agent.id)
+        .bind(agent.created_at)
+        .bind(agent.updated_at)
+        .bind(agent.name)
+        .bind(agent.default_user_name)
+        .bind(agent.default_constitution)
+        .bind(agent.default_memory_size as i64)
+        .bind(
+            agent
+                .llm_configuration
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(serde_json::to_string(&agent.quota)?)
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    async fn get_agent(&self, id: Uuid) -> Result<Option<AgentRecord>, MyselfError> {
+        let row = query_as::<_, AgentRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(AgentRecord::try_from).transpose()
+    }
+
+    async fn get_agent_by_name(&self, name: &str) -> Result<Option<AgentRecord>, MyselfError> {
+        let row = query_as::<_, AgentRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            WHERE name = $1
+            ORDER BY created_at ASC, id ASC
+            LIMIT 1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(AgentRecord::try_from).transpose()
+    }
+
+    async fn list_agents(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<AgentRecord>, MyselfError> {
+        let rows = query_as::<_, AgentRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            FROM agents
+            ORDER BY created_at ASC, id ASC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(AgentRecord::try_from).collect()
+    }
+
+    async fn update_agent(&self, agent: AgentRecord) -> Result<Option<AgentRecord>, MyselfError> {
+        let row = query_as::<_, AgentRow>(
+            r#"
+            UPDATE agents
+            SET updated_at = $2, name = $3, default_interaction_user_name = $4, default_interaction_constitution = $5, default_interaction_memory_size = $6, llm_configuration = $7, quota = $8
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, name, default_interaction_user_name, default_interaction_constitution, default_interaction_memory_size, llm_configuration, quota
+            "#,
+        )
+        .bind(agent.id)
+        .bind(agent.updated_at)
+        .bind(agent.name)
+        .bind(agent.default_user_name)
+        .bind(agent.default_constitution)
+        .bind(agent.default_memory_size as i64)
+        .bind(
+            agent
+                .llm_configuration
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(serde_json::to_string(&agent.quota)?)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(AgentRecord::try_from).transpose()
+    }
+
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, MyselfError> {
+        let mut tx = self.pool.begin().await?;
+
+        query(
+            r#"
+            DELETE FROM meta
+            WHERE agent_id = $1 OR default_interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        query(
+            r#"
+            DELETE FROM interaction_members
+            WHERE agent_id = $1 OR interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        query(
+            r#"
+            DELETE FROM interaction_blocks
+            WHERE interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        query(
+            r#"
+            DELETE FROM summary_usage
+            WHERE interaction_id IN (SELECT id FROM interactions WHERE agent_id = $1)
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        // Keeps what the agent wrote in other agents' group interactions.
+        query(
+            r#"
+            UPDATE interaction_blocks SET agent_id = NULL
+            WHERE agent_id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        query(
+            r#"
+            UPDATE summary_usage SET agent_id = NULL
+            WHERE agent_id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        query(
+            r#"
+            DELETE FROM interactions
+            WHERE agent_id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+
+        let deleted = query(
+            r#"
+            DELETE FROM agents
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        tx.commit().await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn insert_interaction(
+        &self,
+        interaction: InteractionRecord,
+    ) -> Result<InteractionRecord, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            INSERT INTO interactions (id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(interaction.id)
+        .bind(interaction.created_at)
+        .bind(interaction.updated_at)
+        .bind(interaction.user_name)
+        .bind(interaction.long_term_memory_size as i64)
+        .bind(interaction.constitution)
+        .bind(interaction.short_term_memory)
+        .bind(interaction.agent_id)
+        .bind(interaction.summarized_until.map(|cursor| cursor.created_at))
+        .bind(interaction.summarized_until.map(|cursor| cursor.id))
+        .bind(serde_json::to_string(&interaction.llm_overrides)?)
+        .bind(serde_json::to_string(&interaction.quota)?)
+        .bind(
+            interaction
+                .prompt_template
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?,
+        )
+        .bind(interaction.participant_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    async fn get_interaction(&self, id: Uuid) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn get_agent_interactions(
+        &self,
+        agent_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        let order_by = match options.order {
+            InteractionsOrder::CreatedAtAsc => "created_at ASC",
+            InteractionsOrder::CreatedAtDesc => "created_at DESC",
+            InteractionsOrder::UpdatedAtAsc => "updated_at ASC",
+            InteractionsOrder::UpdatedAtDesc => "updated_at DESC",
+        };
+
+        let rows = query_as::<_, InteractionRow>(&format!(
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE agent_id = $1
+            ORDER BY {}, id
+            LIMIT $2
+            OFFSET $3
+            "#,
+            order_by
+        ))
+        .bind(agent_id)
+        .bind(options.limit as i64)
+        .bind(options.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(InteractionRecord::try_from).collect()
+    }
+
+    async fn update_constitution(
+        &self,
+        id: Uuid,
+        constitution: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET constitution = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(constitution)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn set_short_term_memory(
+        &self,
+        id: Uuid,
+        memory: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET short_term_memory = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(memory)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn set_llm_overrides(
+        &self,
+        id: Uuid,
+        overrides: LLMOverrides,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET llm_overrides = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(serde_json::to_string(&overrides)?)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn set_quota(
+        &self,
+        id: Uuid,
+        quota: Quota,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET quota = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(serde_json::to_string(&quota)?)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn set_prompt_template(
+        &self,
+        id: Uuid,
+        template: Option<PromptTemplate>,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET prompt_template = $2, updated_at = $3
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(template.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn set_summary(
+        &self,
+        id: Uuid,
+        summary: String,
+        summarized_until: HistoryCursor,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError> {
+        let row = query_as::<_, InteractionRow>(
+            r#"
+            UPDATE interactions
+            SET short_term_memory = $2, summarized_until_created_at = $3, summarized_until_id = $4, updated_at = $5
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            "#,
+        )
+        .bind(id)
+        .bind(summary)
+        .bind(summarized_until.created_at)
+        .bind(summarized_until.id)
+        .bind(updated_at)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(InteractionRecord::try_from).transpose()
+    }
+
+    async fn append_block(
+        &self,
+        interaction_id: Uuid,
+        block: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        let row = query_as::<_, InteractionBlockRow>(
+            r#"
+            INSERT INTO interaction_blocks (id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id, embedding)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15) RETURNING id, created_at, updated_at, interaction_id, role, content, name, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            "#,
+        )
+        .bind(block.id)
+        .bind(block.created_at)
+        .bind(block.updated_at)
+        .bind(interaction_id)
+        .bind(block.role.as_str())
+        .bind(&block.content)
+        .bind(&block.name)
+        .bind(&block.tool_call_id)
+        .bind(block.usage.as_ref().map(|usage| usage.model_name.clone()))
+        .bind(block.usage.as_ref().map(|usage| usage.prompt_tokens as i64))
+        .bind(block.usage.as_ref().map(|usage| usage.completion_tokens as i64))
+        .bind(
+            block
+                .usage
+                .as_ref()
+                .and_then(|usage| usage.finish_reason.clone()),
+        )
+        .bind(block.participant_id)
+        .bind(block.agent_id)
+        .bind(block.embedding.as_deref().map(embedding_to_bytes))
+        .fetch_one(&self.pool)
+        .await?;
+
+        query(
+            r#"
+            UPDATE interactions
+            SET updated_at = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(row.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(InteractionBlock {
+            embedding: block.embedding.clone(),
+            ..row.try_into()?
+        })
+    }
+
+    async fn get_long_term_memory(
+        &self,
+        interaction_id: Uuid,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        let mut blocks = query_as::<_, InteractionBlockRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, interaction_id, role, content, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            FROM interaction_blocks
+            WHERE interaction_id = $1
+                AND ($2 IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(before.map(|cursor| cursor.created_at))
+        .bind(before.map(|cursor| cursor.id))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionBlock::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+
+        blocks.reverse();
+
+        Ok(blocks)
+    }
+
+    async fn get_blocks_after(
+        &self,
+        interaction_id: Uuid,
+        after: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        query_as::<_, InteractionBlockRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, interaction_id, role, content, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id
+            FROM interaction_blocks
+            WHERE interaction_id = $1
+                AND ($2 IS NULL OR (created_at, id) > ($2, $3))
+            ORDER BY created_at ASC, id ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(after.map(|cursor| cursor.created_at))
+        .bind(after.map(|cursor| cursor.id))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(InteractionBlock::try_from)
+        .collect()
+    }
+
+    async fn append_summary_usage(
+        &self,
+        interaction_id: Uuid,
+        agent_id: Option<Uuid>,
+        usage: &TokenUsage,
+        created_at: DateTime<Utc>,
+    ) -> Result<(), MyselfError> {
+        query(
+            r#"
+            INSERT INTO summary_usage (id, created_at, interaction_id, agent_id, model_name, prompt_tokens, completion_tokens, finish_reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(created_at)
+        .bind(interaction_id)
+        .bind(agent_id)
+        .bind(&usage.model_name)
+        .bind(usage.prompt_tokens as i64)
+        .bind(usage.completion_tokens as i64)
+        .bind(&usage.finish_reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_interaction_usage(
+        &self,
+        interaction_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        let rows = query_as::<_, ModelUsageRow>(
+            r#"
+            SELECT model_name, COUNT(*) AS responses, SUM(prompt_tokens) AS prompt_tokens, SUM(completion_tokens) AS completion_tokens
+            FROM (
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM interaction_blocks
+                WHERE interaction_id = $1 AND model_name IS NOT NULL
+                    AND ($2 IS NULL OR created_at >= $2)
+                UNION ALL
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM summary_usage
+                WHERE interaction_id = $1
+                    AND ($2 IS NULL OR created_at >= $2)
+            )
+            GROUP BY model_name
+            ORDER BY model_name
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ModelUsage::from).collect())
+    }
+
+    async fn get_agent_usage(
+        &self,
+        agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError> {
+        let rows = query_as::<_, ModelUsageRow>(
+            r#"
+            SELECT model_name, COUNT(*) AS responses, SUM(prompt_tokens) AS prompt_tokens, SUM(completion_tokens) AS completion_tokens
+            FROM (
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM interaction_blocks
+                WHERE agent_id = $1 AND model_name IS NOT NULL
+                    AND ($2 IS NULL OR created_at >= $2)
+                UNION ALL
+                SELECT model_name, prompt_tokens, completion_tokens
+                FROM summary_usage
+                WHERE agent_id = $1
+                    AND ($2 IS NULL OR created_at >= $2)
+            )
+            GROUP BY model_name
+            ORDER BY model_name
+            "#,
+        )
+        .bind(agent_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ModelUsage::from).collect())
+    }
+
+    async fn search_blocks(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError> {
+        let rows = query_as::<_, EmbeddedBlockRow>(
+            r#"
+            SELECT id, created_at, updated_at, name, interaction_id, role, content, tool_call_id, model_name, prompt_tokens, completion_tokens, finish_reason, participant_id, agent_id, embedding
+            FROM interaction_blocks
+            WHERE interaction_id = $1 AND embedding IS NOT NULL
+                AND ($2 IS NULL OR (created_at, id) < ($2, $3))
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(before.map(|cursor| cursor.created_at))
+        .bind(before.map(|cursor| cursor.id))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let candidates = rows
+            .into_iter()
+            .map(|row| {
+                let embedding = embedding_from_bytes(&row.embedding);
+                Ok((InteractionBlock::try_from(row.block)?, embedding))
+            })
+            .collect::<Result<Vec<_>, MyselfError>>()?;
+
+        Ok(most_similar(candidates.into_iter(), embedding, limit))
+    }
+
+    async fn flush_long_term_memory(&self, interaction_id: Uuid) -> Result<(), MyselfError> {
+        query(
+            r#"
+            DELETE FROM interaction_blocks
+            WHERE interaction_id = $1
+            "#,
+        )
+        .bind(interaction_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_meta(&self, agent_id: Uuid) -> Result<Option<Meta>, MyselfError> {
+        let row = query_as::<_, MetaRow>(
+            r#"
+            SELECT id, created_at, updated_at, agent_id, default_interaction_id
+            FROM meta
+            WHERE agent_id = $1
+            "#,
+        )
+        .bind(agent_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Meta::from))
+    }
+
+    async fn insert_meta(&self, meta: Meta) -> Result<Meta, MyselfError> {
+        let row = query_as::<_, MetaRow>(
+            r#"
+            INSERT INTO meta (id, created_at, updated_at, agent_id, default_interaction_id)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at, updated_at, agent_id, default_interaction_id
+            "#,
+        )
+        .bind(meta.id)
+        .bind(meta.created_at)
+        .bind(meta.updated_at)
+        .bind(meta.agent_id)
+        .bind(meta.default_interaction_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    async fn set_default_interaction(
+        &self,
+        meta_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<Option<Meta>, MyselfError> {
+        let row = query_as::<_, MetaRow>(
+            r#"
+            UPDATE meta
+            SET default_interaction_id = $1, updated_at = $3
+            WHERE id = $2
+            RETURNING id, created_at, updated_at, agent_id, default_interaction_id
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(meta_id)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(Meta::from))
+    }
+
+    async fn insert_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError> {
+        let row = query_as::<_, ParticipantRow>(
+            r#"
+            INSERT INTO participants (id, created_at, updated_at, display_name, external_id, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING id, created_at, updated_at, display_name, external_id, metadata
+            "#,
+        )
+        .bind(participant.id)
+        .bind(participant.created_at)
+        .bind(participant.updated_at)
+        .bind(participant.display_name)
+        .bind(participant.external_id)
+        .bind(serde_json::to_string(&participant.metadata)?)
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    async fn get_participant(&self, id: Uuid) -> Result<Option<Participant>, MyselfError> {
+        let row = query_as::<_, ParticipantRow>(
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(Participant::try_from).transpose()
+    }
+
+    async fn get_participant_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Result<Option<Participant>, MyselfError> {
+        let row = query_as::<_, ParticipantRow>(
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            WHERE external_id = $1
+            "#,
+        )
+        .bind(external_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(Participant::try_from).transpose()
+    }
+
+    async fn list_participants(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Participant>, MyselfError> {
+        let rows = query_as::<_, ParticipantRow>(
+            r#"
+            SELECT id, created_at, updated_at, display_name, external_id, metadata
+            FROM participants
+            ORDER BY created_at ASC, id ASC
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Participant::try_from).collect()
+    }
+
+    async fn update_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Option<Participant>, MyselfError> {
+        let row = query_as::<_, ParticipantRow>(
+            r#"
+            UPDATE participants
+            SET updated_at = $2, display_name = $3, external_id = $4, metadata = $5
+            WHERE id = $1
+            RETURNING id, created_at, updated_at, display_name, external_id, metadata
+            "#,
+        )
+        .bind(participant.id)
+        .bind(participant.updated_at)
+        .bind(participant.display_name)
+        .bind(participant.external_id)
+        .bind(serde_json::to_string(&participant.metadata)?)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(Participant::try_from).transpose()
+    }
+
+    async fn get_participant_interactions(
+        &self,
+        participant_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError> {
+        let order_by = match options.order {
+            InteractionsOrder::CreatedAtAsc => "created_at ASC",
+            InteractionsOrder::CreatedAtDesc => "created_at DESC",
+            InteractionsOrder::UpdatedAtAsc => "updated_at ASC",
+            InteractionsOrder::UpdatedAtDesc => "updated_at DESC",
+        };
+
+        let rows = query_as::<_, InteractionRow>(&format!(
+            r#"
+            SELECT id, created_at, updated_at, user_name, default_long_term_memory_size, constitution, short_term_memory, agent_id, summarized_until_created_at, summarized_until_id, llm_overrides, quota, prompt_template, participant_id
+            FROM interactions
+            WHERE participant_id = $1 OR id IN (SELECT interaction_id FROM interaction_blocks WHERE participant_id = $1)
+            ORDER BY {}, id
+            LIMIT $2
+            OFFSET $3
+            "#,
+            order_by
+        ))
+        .bind(participant_id)
+        .bind(options.limit as i64)
+        .bind(options.offset as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(InteractionRecord::try_from).collect()
+    }
+
+    async fn insert_member(
+        &self,
+        member: InteractionMember,
+    ) -> Result<InteractionMember, MyselfError> {
+        let row = query_as::<_, MemberRow>(
+            r#"
+            INSERT INTO interaction_members (id, created_at, interaction_id, participant_id, agent_id)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id, created_at, interaction_id, participant_id, agent_id
+            "#,
+        )
+        .bind(member.id)
+        .bind(member.created_at)
+        .bind(member.interaction_id)
+        .bind(member.member.participant_id())
+        .bind(member.member.agent_id())
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_into()
+    }
+
+    async fn get_members(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<Vec<InteractionMember>, MyselfError> {
+        let rows = query_as::<_, MemberRow>(
+            r#"
+            SELECT id, created_at, interaction_id, participant_id, agent_id
+            FROM interaction_members
+            WHERE interaction_id = $1
+            ORDER BY created_at ASC, id ASC
+            "#,
+        )
+        .bind(interaction_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(InteractionMember::try_from).collect()
+    }
+
+    async fn delete_member(
+        &self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<bool, MyselfError> {
+        let deleted = query(
+            r#"
+            DELETE FROM interaction_members
+            WHERE interaction_id = $1 AND (participant_id = $2 OR agent_id = $3)
+            "#,
+        )
+        .bind(interaction_id)
+        .bind(member.participant_id())
+        .bind(member.agent_id())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(deleted > 0)
+    }
+}
@@ -0,0 +1,383 @@
+use std::{fmt::Debug, marker::PhantomData};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    backend::core::AgentBackend,
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMOverrides},
+    sdk::{
+        group::{InteractionMember, Member},
+        interaction::{
+            HistoryCursor, Interaction, InteractionBlock, InteractionsQuery, Meta, WithoutAgent,
+        },
+        participant::Participant,
+        quota::Quota,
+        template::PromptTemplate,
+        usage::{ModelUsage, TokenUsage},
+    },
+};
+
+#[derive(Clone, Debug)]
+pub struct AgentRecord {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub name: String,
+
+    pub default_user_name: String,
+    pub default_constitution: String,
+    pub default_memory_size: usize,
+
+    pub llm_configuration: Option<LLMConfiguration>,
+    pub quota: Quota,
+}
+
+#[derive(Clone, Debug)]
+pub struct InteractionRecord {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+
+    pub user_name: String,
+    pub constitution: String,
+
+    pub short_term_memory: String,
+    pub summarized_until: Option<HistoryCursor>,
+    pub long_term_memory_size: usize,
+    pub llm_overrides: LLMOverrides,
+    pub quota: Quota,
+    pub prompt_template: Option<PromptTemplate>,
+
+    pub agent_id: Option<Uuid>,
+    pub participant_id: Option<Uuid>,
+}
+
+// LLM settings are stored as JSON text, which both databases support as-is.
+pub(crate) fn llm_configuration_from_column(
+    value: Option<String>,
+) -> Result<Option<LLMConfiguration>, MyselfError> {
+    Ok(value.as_deref().map(serde_json::from_str).transpose()?)
+}
+
+pub(crate) fn token_usage_from_columns(
+    model_name: Option<String>,
+    prompt_tokens: Option<i64>,
+    completion_tokens: Option<i64>,
+    finish_reason: Option<String>,
+) -> Option<TokenUsage> {
+    Some(TokenUsage {
+        model_name: model_name?,
+        prompt_tokens: prompt_tokens? as usize,
+        completion_tokens: completion_tokens? as usize,
+        finish_reason,
+    })
+}
+
+pub(crate) fn quota_from_column(value: Option<String>) -> Result<Quota, MyselfError> {
+    Ok(value
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+// `None` for embeddings of different models, which are not comparable.
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() {
+        return None;
+    }
+
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm =
+        a.iter().map(|a| a * a).sum::<f32>().sqrt() * b.iter().map(|b| b * b).sum::<f32>().sqrt();
+
+    (norm > 0.0).then(|| dot / norm)
+}
+
+// Brute-force search for the stores without a vector index.
+pub(crate) fn most_similar(
+    candidates: impl Iterator<Item = (InteractionBlock, Vec<f32>)>,
+    embedding: &[f32],
+    limit: usize,
+) -> Vec<(InteractionBlock, f32)> {
+    let mut ranked = candidates
+        .filter_map(|(block, candidate)| {
+            cosine_similarity(&candidate, embedding).map(|similarity| (block, similarity))
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranked.truncate(limit);
+    ranked
+}
+
+pub(crate) fn prompt_template_from_column(
+    value: Option<String>,
+) -> Result<Option<PromptTemplate>, MyselfError> {
+    Ok(value.as_deref().map(serde_json::from_str).transpose()?)
+}
+
+pub(crate) fn metadata_from_column(value: String) -> Result<Map<String, Value>, MyselfError> {
+    Ok(serde_json::from_str(&value)?)
+}
+
+// Members are stored with exactly one of a participant or an agent id.
+pub(crate) fn member_from_columns(
+    participant_id: Option<Uuid>,
+    agent_id: Option<Uuid>,
+) -> Result<Member, MyselfError> {
+    match (participant_id, agent_id) {
+        (Some(id), None) => Ok(Member::Participant(id)),
+        (None, Some(id)) => Ok(Member::Agent(id)),
+        _ => Err(MyselfError::Configuration(
+            "interaction member must be either a participant or an agent".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn llm_overrides_from_column(
+    value: Option<String>,
+) -> Result<LLMOverrides, MyselfError> {
+    Ok(value
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+#[async_trait]
+pub trait MemoryStore
+where
+    Self: Debug + Send + Sync,
+{
+    async fn schema_version(&self) -> Result<Option<i64>, MyselfError>;
+
+    async fn insert_agent(&self, agent: AgentRecord) -> Result<AgentRecord, MyselfError>;
+
+    async fn get_agent(&self, id: Uuid) -> Result<Option<AgentRecord>, MyselfError>;
+
+    // The oldest agent named `name`, as names are not unique.
+    async fn get_agent_by_name(&self, name: &str) -> Result<Option<AgentRecord>, MyselfError>;
+
+    // Agents in creation order.
+    async fn list_agents(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<AgentRecord>, MyselfError>;
+
+    // Replaces everything but the id and creation time.
+    async fn update_agent(&self, agent: AgentRecord) -> Result<Option<AgentRecord>, MyselfError>;
+
+    // Deletes the agent along with its meta, its memberships, its interactions
+    // and their blocks and members. Returns whether the agent existed.
+    async fn delete_agent(&self, id: Uuid) -> Result<bool, MyselfError>;
+
+    async fn insert_interaction(
+        &self,
+        interaction: InteractionRecord,
+    ) -> Result<InteractionRecord, MyselfError>;
+
+    async fn get_interaction(&self, id: Uuid) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn get_agent_interactions(
+        &self,
+        agent_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError>;
+
+    async fn update_constitution(
+        &self,
+        id: Uuid,
+        constitution: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn set_short_term_memory(
+        &self,
+        id: Uuid,
+        memory: String,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn set_llm_overrides(
+        &self,
+        id: Uuid,
+        overrides: LLMOverrides,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn set_quota(
+        &self,
+        id: Uuid,
+        quota: Quota,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn set_prompt_template(
+        &self,
+        id: Uuid,
+        template: Option<PromptTemplate>,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn set_summary(
+        &self,
+        id: Uuid,
+        summary: String,
+        summarized_until: HistoryCursor,
+        updated_at: DateTime<Utc>,
+    ) -> Result<Option<InteractionRecord>, MyselfError>;
+
+    async fn append_block(
+        &self,
+        interaction_id: Uuid,
+        block: &InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError>;
+
+    // Returns the `limit` most recent blocks older than `before` (or the newest
+    // blocks when no cursor is given), in chronological order.
+    async fn get_long_term_memory(
+        &self,
+        interaction_id: Uuid,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError>;
+
+    // Returns up to `limit` of the oldest blocks newer than `after`, in
+    // chronological order.
+    async fn get_blocks_after(
+        &self,
+        interaction_id: Uuid,
+        after: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError>;
+
+    // Records the usage of a call that condensed the interaction's history
+    // into its short-term memory, made for `agent_id`. Usage reports count it
+    // along with the blocks.
+    async fn append_summary_usage(
+        &self,
+        interaction_id: Uuid,
+        agent_id: Option<Uuid>,
+        usage: &TokenUsage,
+        created_at: DateTime<Utc>,
+    ) -> Result<(), MyselfError>;
+
+    // Usage of the interaction's blocks and summaries created since `since`
+    // (or ever), one entry per model.
+    async fn get_interaction_usage(
+        &self,
+        interaction_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError>;
+
+    // Usage of the blocks the agent wrote and the summaries made for it, in
+    // any interaction, created since `since` (or ever), one entry per model.
+    async fn get_agent_usage(
+        &self,
+        agent_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ModelUsage>, MyselfError>;
+
+    // Returns up to `limit` embedded blocks older than `before` (or any, when
+    // no cursor is given) with their cosine similarity to `embedding`, most
+    // similar first. Blocks embedded by another model are skipped.
+    async fn search_blocks(
+        &self,
+        interaction_id: Uuid,
+        embedding: &[f32],
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<Vec<(InteractionBlock, f32)>, MyselfError>;
+
+    async fn flush_long_term_memory(&self, interaction_id: Uuid) -> Result<(), MyselfError>;
+
+    async fn get_meta(&self, agent_id: Uuid) -> Result<Option<Meta>, MyselfError>;
+
+    async fn insert_meta(&self, meta: Meta) -> Result<Meta, MyselfError>;
+
+    async fn set_default_interaction(
+        &self,
+        meta_id: Uuid,
+        interaction_id: Uuid,
+    ) -> Result<Option<Meta>, MyselfError>;
+
+    async fn insert_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Participant, MyselfError>;
+
+    async fn get_participant(&self, id: Uuid) -> Result<Option<Participant>, MyselfError>;
+
+    async fn get_participant_by_external_id(
+        &self,
+        external_id: &str,
+    ) -> Result<Option<Participant>, MyselfError>;
+
+    // Participants in creation order.
+    async fn list_participants(
+        &self,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Participant>, MyselfError>;
+
+    // Replaces everything but the id and creation time.
+    async fn update_participant(
+        &self,
+        participant: Participant,
+    ) -> Result<Option<Participant>, MyselfError>;
+
+    // Interactions held with the participant or containing blocks they wrote.
+    async fn get_participant_interactions(
+        &self,
+        participant_id: Uuid,
+        options: &InteractionsQuery,
+    ) -> Result<Vec<InteractionRecord>, MyselfError>;
+
+    async fn insert_member(
+        &self,
+        member: InteractionMember,
+    ) -> Result<InteractionMember, MyselfError>;
+
+    // Members in the order they joined.
+    async fn get_members(
+        &self,
+        interaction_id: Uuid,
+    ) -> Result<Vec<InteractionMember>, MyselfError>;
+
+    // Returns whether the member was part of the interaction.
+    async fn delete_member(
+        &self,
+        interaction_id: Uuid,
+        member: Member,
+    ) -> Result<bool, MyselfError>;
+}
+
+impl<Backend> From<InteractionRecord> for Interaction<Backend, WithoutAgent>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    fn from(record: InteractionRecord) -> Self {
+        Self {
+            id: record.id,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            user_name: record.user_name,
+            constitution: record.constitution,
+            short_term_memory: record.short_term_memory,
+            summarized_until: record.summarized_until,
+            long_term_memory_size: record.long_term_memory_size,
+            llm_overrides: record.llm_overrides,
+            quota: record.quota,
+            prompt_template: record.prompt_template,
+            participant_id: record.participant_id,
+            state: PhantomData,
+            agent: None,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use thiserror::Error;
+
+use crate::llm::LLMEngineError;
+use crate::sdk::quota::{QuotaLimit, QuotaScope};
+
+#[derive(Error, Debug)]
+pub enum MyselfError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Backend error: {0}")]
+    Backend(#[from] LLMEngineError),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Invalid role: {0}")]
+    InvalidRole(String),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Configuration error: {0}")]
+    Configuration(String),
+    #[error("Tool calls did not finish after {0} iterations")]
+    ToolIterationsExceeded(usize),
+    #[error("Quota exceeded for {scope}: {limit}")]
+    QuotaExceeded {
+        scope: QuotaScope,
+        limit: QuotaLimit,
+    },
+}
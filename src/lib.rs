@@ -1,6 +1,7 @@
 pub mod backend;
 pub mod config;
 pub mod database;
+pub mod error;
 pub mod llm;
 pub mod llm_responses;
 pub mod sdk;
@@ -1,10 +1,24 @@
-use reqwest::{header::HeaderMap, Client};
-use serde_json::{from_str, json};
+use async_stream::try_stream;
+use futures::{Stream, StreamExt};
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::HeaderMap, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, from_str, from_value, json, Value};
 use thiserror::Error;
 
-use crate::llm_responses::CompletionResponse;
+use crate::llm_responses::{
+    ChatCompletionChunk, ChatCompletionResponse, ChatMessage, ChatTool, CompletionResponse,
+    EmbeddingResponse, OllamaChatResponse, OllamaEmbedResponse,
+};
+
+static OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+static DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-ada-002";
 
-static OPENAI_COMPLETION_API: &str = "https://api.openai.com/v1/completions";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[derive(Error, Debug)]
 pub enum LLMEngineError {
@@ -14,63 +28,540 @@ pub enum LLMEngineError {
     Json(#[from] serde_json::Error),
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("Context window exceeded: {required} tokens required, {available} available")]
+    ContextWindowExceeded { required: usize, available: usize },
+    #[error("Request timed out after {0:?}")]
+    Timeout(Duration),
 }
 
-#[derive(Debug, Clone)]
+// How failed requests are retried. Rate limits (429), server errors (5xx),
+// timeouts and connection failures are retried; other errors are returned
+// right away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    // Waits a random duration between half and all of each backoff, so clients
+    // that failed together do not retry together.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    // The wait before the given retry, counting from 0.
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .mul_f64(self.multiplier.powi(retry as i32))
+            .min(self.max_backoff);
+
+        if self.jitter {
+            backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+        } else {
+            backoff
+        }
+    }
+
+    // The wait before the given retry. A wait requested by the server is
+    // followed, but never for longer than `max_backoff`.
+    fn wait(&self, retry: usize, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(wait) => wait.min(self.max_backoff),
+            None => self.backoff(retry),
+        }
+    }
+
+    pub fn is_retryable(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::REQUEST_TIMEOUT
+                | StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+}
+
+// The wait requested by the server, from `retry-after-ms` or `Retry-After` as
+// either seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+
+    if let Some(millis) = header("retry-after-ms").and_then(|value| value.parse::<f64>().ok()) {
+        return Duration::try_from_secs_f64(millis / 1000.0).ok();
+    }
+
+    let value = header("retry-after")?;
+
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+// Providers report errors as `{"error": {"message": ...}}` (OpenAI) or
+// `{"error": "..."}` (Ollama).
+fn error_message(value: &Value) -> Option<String> {
+    match &value["error"] {
+        Value::String(message) => Some(message.clone()),
+        Value::Object(error) => Some(
+            error
+                .get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| Value::Object(error.clone()).to_string()),
+        ),
+        _ => None,
+    }
+}
+
+fn provider_error(status: StatusCode, body: &str) -> LLMEngineError {
+    let message = from_str::<Value>(body)
+        .ok()
+        .as_ref()
+        .and_then(error_message)
+        .unwrap_or_else(|| body.trim().to_string());
+
+    LLMEngineError::InvalidResponse(format!("{}: {}", status, message))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct LLMConfiguration {
     pub model_name: String,
     pub max_tokens: usize,
     pub temperature: f32,
     pub top_p: Option<f32>,
+    pub context_window: Option<usize>,
+    pub stop: Option<Vec<String>>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
 }
 
 impl Default for LLMConfiguration {
     fn default() -> Self {
-        let model_name =
-            std::env::var("OPENAI_MODEL_NAME").unwrap_or("text-davinci-003".to_string());
+        Self {
+            model_name: "gpt-3.5-turbo".to_string(),
+            max_tokens: 1000,
+            temperature: 0.75,
+            top_p: None,
+            context_window: None,
+            stop: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+        }
+    }
+}
 
-        let max_tokens = std::env::var("OPENAI_MAX_TOKENS")
-            .unwrap_or("1000".to_string())
-            .parse::<usize>()
-            .unwrap();
+fn env_var<T>(name: &str) -> Result<Option<T>, LLMEngineError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value.parse::<T>().map(Some).map_err(|err| {
+            LLMEngineError::InvalidConfiguration(format!("{} is invalid: {}", name, err))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+impl LLMConfiguration {
+    // Reads the `OPENAI_*` environment variables on top of the defaults.
+    pub fn from_env() -> Result<Self, LLMEngineError> {
+        let defaults = Self::default();
 
-        let temperature = std::env::var("OPENAI_TEMPERATURE")
-            .unwrap_or("0.75".to_string())
-            .parse::<f32>()
-            .unwrap();
+        let configuration = Self {
+            model_name: env_var("OPENAI_MODEL_NAME")?.unwrap_or(defaults.model_name),
+            max_tokens: env_var("OPENAI_MAX_TOKENS")?.unwrap_or(defaults.max_tokens),
+            temperature: env_var("OPENAI_TEMPERATURE")?.unwrap_or(defaults.temperature),
+            top_p: env_var("OPENAI_TOP_P")?,
+            context_window: env_var("OPENAI_CONTEXT_WINDOW")?,
+            stop: None,
+            presence_penalty: env_var("OPENAI_PRESENCE_PENALTY")?,
+            frequency_penalty: env_var("OPENAI_FREQUENCY_PENALTY")?,
+        };
+
+        configuration.validate()?;
+
+        Ok(configuration)
+    }
+
+    pub fn with_overrides(&self, overrides: &LLMOverrides) -> Self {
+        let overrides = overrides.clone();
 
         Self {
-            model_name,
-            max_tokens,
-            temperature,
-            top_p: None,
+            model_name: overrides.model_name.unwrap_or(self.model_name.clone()),
+            max_tokens: overrides.max_tokens.unwrap_or(self.max_tokens),
+            temperature: overrides.temperature.unwrap_or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            context_window: overrides.context_window.or(self.context_window),
+            stop: overrides.stop.or(self.stop.clone()),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LLMEngineError> {
+        let in_range = |name: &str, value: Option<f32>, min: f32, max: f32| match value {
+            Some(value) if !(min..=max).contains(&value) => {
+                Err(LLMEngineError::InvalidConfiguration(format!(
+                    "{} must be between {} and {}, got {}",
+                    name, min, max, value
+                )))
+            }
+            _ => Ok(()),
+        };
+
+        if self.model_name.trim().is_empty() {
+            return Err(LLMEngineError::InvalidConfiguration(
+                "model name is empty".to_string(),
+            ));
+        }
+
+        if self.max_tokens == 0 {
+            return Err(LLMEngineError::InvalidConfiguration(
+                "max_tokens must be greater than 0".to_string(),
+            ));
         }
+
+        in_range("temperature", Some(self.temperature), 0.0, 2.0)?;
+        in_range("top_p", self.top_p, 0.0, 1.0)?;
+        in_range("presence_penalty", self.presence_penalty, -2.0, 2.0)?;
+        in_range("frequency_penalty", self.frequency_penalty, -2.0, 2.0)
+    }
+}
+
+// Per-interaction changes to the agent's configuration. Unset fields keep the
+// agent's value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LLMOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+}
+
+impl LLMOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), LLMEngineError> {
+        LLMConfiguration::default().with_overrides(self).validate()
     }
 }
 
-#[derive(Debug, Clone, Default)]
+// The wire format spoken by the server behind `base_url`. `OpenAI` covers the
+// OpenAI API itself and compatible servers such as llama.cpp or vLLM, while
+// `Ollama` targets Ollama's native `/api/chat`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LLMDialect {
+    #[default]
+    OpenAI,
+    Ollama,
+}
+
+#[derive(Debug, Clone)]
 pub struct LLMEngine {
     access_token: String,
+    base_url: String,
+    dialect: LLMDialect,
     http_client: Client,
     configuration: LLMConfiguration,
+    embedding_model: String,
+    retry_policy: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+impl Default for LLMEngine {
+    fn default() -> Self {
+        Self::unconfigured(String::new())
+    }
 }
 
 impl LLMEngine {
-    pub fn new(access_token: String) -> Self {
+    // Reads the configuration and embedding model from the `OPENAI_*`
+    // environment variables on top of the defaults.
+    pub fn new(access_token: String) -> Result<Self, LLMEngineError> {
+        let mut engine =
+            Self::unconfigured(access_token).with_configuration(LLMConfiguration::from_env()?);
+
+        if let Some(embedding_model) = env_var::<String>("OPENAI_EMBEDDING_MODEL")? {
+            engine = engine.with_embedding_model(embedding_model);
+        }
+
+        Ok(engine)
+    }
+
+    fn unconfigured(access_token: String) -> Self {
         Self {
             access_token,
+            base_url: std::env::var("OPENAI_API_BASE")
+                .unwrap_or(OPENAI_API_BASE.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            dialect: LLMDialect::OpenAI,
             http_client: Client::new(),
-            // TODO: Make this more configurable
             configuration: LLMConfiguration::default(),
+            embedding_model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            retry_policy: RetryPolicy::default(),
+            timeout: Some(DEFAULT_TIMEOUT),
         }
     }
 
-    pub fn new_defaults() -> Self {
-        Self {
-            access_token: std::env::var("OPENAI_API_KEY").unwrap(),
-            http_client: Client::new(),
-            configuration: LLMConfiguration::default(),
+    // Targets a self-hosted server, which usually needs no access token.
+    pub fn local(
+        base_url: impl Into<String>,
+        dialect: LLMDialect,
+        model_name: impl Into<String>,
+    ) -> Self {
+        Self::unconfigured(String::new())
+            .with_base_url(base_url)
+            .with_dialect(dialect)
+            .with_model(model_name)
+    }
+
+    pub fn new_defaults() -> Result<Self, LLMEngineError> {
+        let access_token = std::env::var("OPENAI_API_KEY").map_err(|_| {
+            LLMEngineError::InvalidConfiguration("OPENAI_API_KEY is not set".to_string())
+        })?;
+
+        Self::new(access_token)
+    }
+
+    pub fn with_model(mut self, model_name: impl Into<String>) -> Self {
+        self.configuration.model_name = model_name.into();
+        self
+    }
+
+    pub fn with_configuration(mut self, configuration: LLMConfiguration) -> Self {
+        self.configuration = configuration;
+        self
+    }
+
+    pub fn with_embedding_model(mut self, embedding_model: impl Into<String>) -> Self {
+        self.embedding_model = embedding_model.into();
+        self
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_dialect(mut self, dialect: LLMDialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn with_access_token(mut self, access_token: impl Into<String>) -> Self {
+        self.access_token = access_token.into();
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    // Bounds each attempt until the response headers arrive, and the reading
+    // of non-streamed bodies. `None` waits indefinitely.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn configuration(&self) -> &LLMConfiguration {
+        &self.configuration
+    }
+
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn dialect(&self) -> LLMDialect {
+        self.dialect
+    }
+
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    fn chat_endpoint(&self) -> String {
+        match self.dialect {
+            LLMDialect::OpenAI => self.endpoint("chat/completions"),
+            LLMDialect::Ollama => self.endpoint("api/chat"),
+        }
+    }
+
+    fn post(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        let request = self.http_client.post(endpoint);
+
+        // Local servers reject or ignore an empty bearer token.
+        if self.access_token.is_empty() {
+            request
+        } else {
+            request.bearer_auth(&self.access_token)
+        }
+    }
+
+    async fn with_timeout_of<T>(
+        &self,
+        future: impl std::future::Future<Output = Result<T, reqwest::Error>>,
+    ) -> Result<T, LLMEngineError> {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .map_err(|_| LLMEngineError::Timeout(timeout))?
+                .map_err(LLMEngineError::from),
+            None => Ok(future.await?),
+        }
+    }
+
+    // Posts `body` until it gets a successful response, following the retry
+    // policy. Failed responses are mapped from the provider's error body.
+    async fn send(&self, endpoint: &str, body: &Value) -> Result<Response, LLMEngineError> {
+        let mut retry = 0;
+
+        loop {
+            let (error, wait) = match self
+                .with_timeout_of(self.post(endpoint).json(body).send())
+                .await
+            {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let wait = retry_after(response.headers());
+                    let error = provider_error(status, &response.text().await.unwrap_or_default());
+
+                    if !RetryPolicy::is_retryable(status) {
+                        return Err(error);
+                    }
+
+                    (error, wait)
+                }
+                Err(LLMEngineError::Http(err)) if !(err.is_connect() || err.is_timeout()) => {
+                    return Err(LLMEngineError::Http(err));
+                }
+                Err(error) => (error, None),
+            };
+
+            if retry >= self.retry_policy.max_retries {
+                return Err(error);
+            }
+
+            tokio::time::sleep(self.retry_policy.wait(retry, wait)).await;
+
+            retry += 1;
+        }
+    }
+
+    fn chat_body(
+        &self,
+        messages: Vec<ChatMessage>,
+        stop_words: Option<Vec<String>>,
+        tools: Vec<ChatTool>,
+        stream: bool,
+    ) -> Value {
+        let stop_words = stop_words.or_else(|| self.configuration.stop.clone());
+
+        let mut body = match self.dialect {
+            LLMDialect::OpenAI => json! {
+                {
+                    "model": self.configuration.model_name,
+                    "messages": messages,
+                    "max_tokens": self.configuration.max_tokens,
+                    "temperature": self.configuration.temperature,
+                    "stop": stop_words,
+                    "top_p": self.configuration.top_p.unwrap_or(1.0),
+                    "stream": stream,
+                }
+            },
+            LLMDialect::Ollama => json! {
+                {
+                    "model": self.configuration.model_name,
+                    "messages": messages.iter().map(ollama_message).collect::<Vec<_>>(),
+                    "stream": stream,
+                    "options": {
+                        "num_predict": self.configuration.max_tokens,
+                        "temperature": self.configuration.temperature,
+                        "stop": stop_words,
+                        "top_p": self.configuration.top_p.unwrap_or(1.0),
+                    },
+                }
+            },
+        };
+
+        // Penalties are only sent when set, since not every server knows them.
+        let penalties = match self.dialect {
+            LLMDialect::OpenAI => &mut body,
+            LLMDialect::Ollama => &mut body["options"],
+        };
+
+        if let Some(presence_penalty) = self.configuration.presence_penalty {
+            penalties["presence_penalty"] = json!(presence_penalty);
+        }
+
+        if let Some(frequency_penalty) = self.configuration.frequency_penalty {
+            penalties["frequency_penalty"] = json!(frequency_penalty);
+        }
+
+        // Streamed replies only report their usage when asked to.
+        if stream && self.dialect == LLMDialect::OpenAI {
+            body["stream_options"] = json!({ "include_usage": true });
         }
+
+        // The API rejects an empty `tools` array, so only send it when needed.
+        if !tools.is_empty() {
+            body["tools"] = json!(tools);
+        }
+
+        body
     }
 
     pub async fn completions_call(
@@ -78,43 +569,431 @@ impl LLMEngine {
         prompt: impl Into<String>,
         stop_words: Option<Vec<String>>,
     ) -> Result<CompletionResponse, LLMEngineError> {
-        let endpoint = String::from(OPENAI_COMPLETION_API);
-
-        let mut headers = HeaderMap::new();
-
-        headers.insert(
-            "Authorization",
-            format!("Bearer {}", self.access_token).parse().unwrap(),
-        );
+        if self.dialect != LLMDialect::OpenAI {
+            return Err(LLMEngineError::InvalidConfiguration(format!(
+                "completions are not supported by the {:?} dialect",
+                self.dialect
+            )));
+        }
 
-        headers.insert("Content-Type", "application/json".parse().unwrap());
+        let endpoint = self.endpoint("completions");
 
-        let response = self
-            .http_client
-            .post(&endpoint)
-            .headers(headers)
-            .json(&json! {
+        let body = json! {
                 {
                     "model": self.configuration.model_name,
                     "prompt": prompt.into(),
                     "max_tokens": self.configuration.max_tokens,
                     "temperature": self.configuration.temperature,
-                    "stop": stop_words,
+                    "stop": stop_words.or_else(|| self.configuration.stop.clone()),
                     "top_p": self.configuration.top_p.unwrap_or(1.0),
+                    "presence_penalty": self.configuration.presence_penalty.unwrap_or(0.0),
+                    "frequency_penalty": self.configuration.frequency_penalty.unwrap_or(0.0),
                     // "n": 1,
                     // "stream": false,
                     // "logprobs": null,
                     // "stop": "\n"
                 }
-            })
-            .send()
+        };
+
+        let response = self.send(&endpoint, &body).await?;
+
+        let response_text = self.with_timeout_of(response.text()).await?;
+
+        let data = from_value::<CompletionResponse>(response_value(response_text.as_bytes())?)?;
+
+        if data.choices.is_empty() {
+            return Err(LLMEngineError::InvalidResponse(
+                "completion response has no choices".to_string(),
+            ));
+        }
+
+        Ok(data)
+    }
+
+    pub async fn chat_completions_call(
+        &self,
+        messages: Vec<ChatMessage>,
+        stop_words: Option<Vec<String>>,
+        tools: Vec<ChatTool>,
+    ) -> Result<ChatCompletionResponse, LLMEngineError> {
+        let response = self
+            .send(
+                &self.chat_endpoint(),
+                &self.chat_body(messages, stop_words, tools, false),
+            )
             .await?;
-        // .unwrap_or_else(op);
 
-        let response_text = response.text().await.unwrap();
+        let response_text = self.with_timeout_of(response.text()).await?;
 
-        let data = from_str::<CompletionResponse>(&response_text).unwrap();
+        let value = response_value(response_text.as_bytes())?;
+
+        let data = match self.dialect {
+            LLMDialect::OpenAI => from_value::<ChatCompletionResponse>(value)?,
+            LLMDialect::Ollama => from_value::<OllamaChatResponse>(value)?.into(),
+        };
+
+        if data.choices.is_empty() {
+            return Err(LLMEngineError::InvalidResponse(
+                "chat completion response has no choices".to_string(),
+            ));
+        }
 
         Ok(data)
     }
+
+    // Embeds each of `inputs` with the embedding model, in the same order.
+    pub async fn embeddings(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, LLMEngineError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let count = inputs.len();
+
+        let endpoint = match self.dialect {
+            LLMDialect::OpenAI => self.endpoint("embeddings"),
+            LLMDialect::Ollama => self.endpoint("api/embed"),
+        };
+
+        let body = json!({
+            "model": self.embedding_model,
+            "input": inputs,
+        });
+
+        let response = self.send(&endpoint, &body).await?;
+
+        let response_text = self.with_timeout_of(response.text()).await?;
+
+        let value = response_value(response_text.as_bytes())?;
+
+        let embeddings = match self.dialect {
+            LLMDialect::OpenAI => {
+                let mut data = from_value::<EmbeddingResponse>(value)?.data;
+                data.sort_by_key(|data| data.index);
+                data.into_iter().map(|data| data.embedding).collect()
+            }
+            LLMDialect::Ollama => from_value::<OllamaEmbedResponse>(value)?.embeddings,
+        };
+
+        if embeddings.len() != count {
+            return Err(LLMEngineError::InvalidResponse(format!(
+                "expected {} embeddings, got {}",
+                count,
+                embeddings.len()
+            )));
+        }
+
+        Ok(embeddings)
+    }
+
+    // Yields the chunks of a streamed chat completion as they arrive, over
+    // server-sent events or, for Ollama, as one JSON object per line. The last
+    // chunk carries the usage of the call when the server reports it.
+    pub async fn chat_completions_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        stop_words: Option<Vec<String>>,
+    ) -> Result<impl Stream<Item = Result<ChatCompletionChunk, LLMEngineError>>, LLMEngineError>
+    {
+        let response = self
+            .send(
+                &self.chat_endpoint(),
+                &self.chat_body(messages, stop_words, Vec::new(), true),
+            )
+            .await?;
+
+        let dialect = self.dialect;
+        let mut bytes = response.bytes_stream();
+
+        Ok(try_stream! {
+            let mut buffer = Vec::new();
+            let mut ended = false;
+
+            while !ended {
+                match bytes.next().await {
+                    Some(chunk) => buffer.extend_from_slice(&chunk?),
+                    // The last line may come without a trailing newline.
+                    None => {
+                        ended = true;
+                        buffer.push(b'\n');
+                    }
+                }
+
+                while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                    let line = buffer.drain(..=end).collect::<Vec<_>>();
+
+                    if dialect == LLMDialect::Ollama {
+                        let line = line.trim_ascii();
+
+                        if line.is_empty() {
+                            continue;
+                        }
+
+                        let chunk = from_value::<OllamaChatResponse>(response_value(line)?)?;
+                        let done = chunk.done;
+
+                        yield chunk.into();
+
+                        if done {
+                            return;
+                        }
+
+                        continue;
+                    }
+
+                    let Some(data) = line.trim_ascii().strip_prefix(b"data:") else {
+                        continue;
+                    };
+
+                    let data = data.trim_ascii();
+
+                    if data == b"[DONE]" {
+                        return;
+                    }
+
+                    yield from_value::<ChatCompletionChunk>(response_value(data)?)?;
+                }
+            }
+        })
+    }
+}
+
+// Ollama expects tool call arguments as JSON objects and has no tool call ids.
+fn ollama_message(message: &ChatMessage) -> Value {
+    let mut value = json! {
+        {
+            "role": message.role,
+            "content": message.content,
+        }
+    };
+
+    if let Some(tool_calls) = &message.tool_calls {
+        value["tool_calls"] = tool_calls
+            .iter()
+            .map(|call| {
+                json! {
+                    {
+                        "function": {
+                            "name": call.function.name,
+                            "arguments": from_str::<Value>(&call.function.arguments)
+                                .unwrap_or_else(|_| json!({})),
+                        }
+                    }
+                }
+            })
+            .collect();
+    }
+
+    value
+}
+
+// Some servers report errors in a successful response, and errors that happen
+// once a stream has started arrive as a chunk of their own.
+fn response_value(data: &[u8]) -> Result<Value, LLMEngineError> {
+    let value = from_slice::<Value>(data)?;
+
+    match error_message(&value) {
+        Some(message) => Err(LLMEngineError::InvalidResponse(message)),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            jitter: false,
+            ..Default::default()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+
+        headers
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_maximum() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn jitter_waits_between_half_and_all_of_the_backoff() {
+        let policy = RetryPolicy::default();
+
+        for _ in 0..100 {
+            let backoff = policy.backoff(1);
+
+            assert!(backoff >= Duration::from_millis(500));
+            assert!(backoff <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn server_waits_are_capped_at_the_maximum_backoff() {
+        let policy = policy();
+
+        assert_eq!(
+            policy.wait(0, Some(Duration::from_secs(5))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            policy.wait(0, Some(Duration::from_secs(3600))),
+            Duration::from_secs(30)
+        );
+        assert_eq!(policy.wait(1, None), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rate_limits_server_errors_and_timeouts_are_retryable() {
+        for status in [
+            StatusCode::REQUEST_TIMEOUT,
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::GATEWAY_TIMEOUT,
+        ] {
+            assert!(RetryPolicy::is_retryable(status), "{}", status);
+        }
+
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+            StatusCode::NOT_IMPLEMENTED,
+        ] {
+            assert!(!RetryPolicy::is_retryable(status), "{}", status);
+        }
+    }
+
+    #[test]
+    fn retry_after_reads_seconds() {
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "2")])),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", " 1.5 ")])),
+            Some(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn retry_after_prefers_milliseconds() {
+        assert_eq!(
+            retry_after(&headers(&[("retry-after-ms", "250")])),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            retry_after(&headers(&[("retry-after", "2"), ("retry-after-ms", "250")])),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn retry_after_reads_http_dates() {
+        let date = (Utc::now() + chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+
+        let wait = retry_after(&headers(&[("retry-after", &date)])).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+
+        let past = (Utc::now() - chrono::Duration::seconds(60))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+
+        assert_eq!(retry_after(&headers(&[("retry-after", &past)])), None);
+    }
+
+    #[test]
+    fn retry_after_ignores_missing_and_invalid_values() {
+        assert_eq!(retry_after(&headers(&[])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "soon")])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "-1")])), None);
+    }
+
+    // The only test that sets these variables, so it cannot race another.
+    #[test]
+    fn new_engines_read_the_environment() {
+        std::env::set_var("OPENAI_MODEL_NAME", "gpt-4");
+        std::env::set_var("OPENAI_MAX_TOKENS", "200");
+
+        let engine = LLMEngine::new("key".to_string()).unwrap();
+        assert_eq!(engine.configuration().model_name, "gpt-4");
+        assert_eq!(engine.configuration().max_tokens, 200);
+
+        std::env::set_var("OPENAI_MAX_TOKENS", "many");
+        assert!(matches!(
+            LLMEngine::new("key".to_string()),
+            Err(LLMEngineError::InvalidConfiguration(_))
+        ));
+
+        std::env::remove_var("OPENAI_MODEL_NAME");
+        std::env::remove_var("OPENAI_MAX_TOKENS");
+
+        assert_eq!(
+            LLMEngine::local("http://localhost:11434", LLMDialect::Ollama, "llama3")
+                .configuration()
+                .model_name,
+            "llama3"
+        );
+    }
+
+    fn message(error: LLMEngineError) -> String {
+        match error {
+            LLMEngineError::InvalidResponse(message) => message,
+            other => panic!("expected an invalid response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn provider_errors_read_the_message_of_either_dialect() {
+        assert_eq!(
+            message(provider_error(
+                StatusCode::TOO_MANY_REQUESTS,
+                r#"{"error": {"message": "Rate limit reached", "type": "requests"}}"#,
+            )),
+            "429 Too Many Requests: Rate limit reached"
+        );
+        assert_eq!(
+            message(provider_error(
+                StatusCode::NOT_FOUND,
+                r#"{"error": "model 'llama3' not found"}"#,
+            )),
+            "404 Not Found: model 'llama3' not found"
+        );
+    }
+
+    #[test]
+    fn provider_errors_fall_back_to_the_body() {
+        assert_eq!(
+            message(provider_error(
+                StatusCode::BAD_REQUEST,
+                r#"{"error": {"code": "bad"}}"#,
+            )),
+            r#"400 Bad Request: {"code":"bad"}"#
+        );
+        assert_eq!(
+            message(provider_error(
+                StatusCode::BAD_GATEWAY,
+                "  upstream unavailable\n",
+            )),
+            "502 Bad Gateway: upstream unavailable"
+        );
+    }
 }
@@ -1,5 +1,8 @@
+use serde::{Deserialize as _, Deserializer};
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -48,3 +51,247 @@ pub struct CompletionChoice {
     #[serde(rename = "finish_reason")]
     pub finish_reason: String,
 }
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: String,
+    // Assistant messages that only call tools come back with a null content.
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(
+        rename = "tool_calls",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub tool_calls: Option<Vec<ChatToolCall>>,
+    #[serde(
+        rename = "tool_call_id",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub tool_call_id: Option<String>,
+}
+
+fn null_as_empty<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: ChatFunctionCall,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatFunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatTool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub function: ChatFunction,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatFunction {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+// OpenAI-compatible servers often omit the bookkeeping fields.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChoice>,
+    pub usage: Usage,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatCompletionChoice {
+    pub index: i64,
+    pub message: ChatMessage,
+    #[serde(rename = "finish_reason")]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    // Only sent, with no choices, in the last chunk of a stream that asked
+    // for it with `stream_options`.
+    pub usage: Option<Usage>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatCompletionChunkChoice {
+    pub index: i64,
+    pub delta: ChatMessageDelta,
+    #[serde(rename = "finish_reason")]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessageDelta {
+    pub role: Option<String>,
+    pub content: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingResponse {
+    pub model: String,
+    pub data: Vec<EmbeddingData>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingData {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+// Response of Ollama's `/api/embed`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaEmbedResponse {
+    pub model: String,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+// Response of Ollama's `/api/chat`, both as a whole and as each line of a
+// streamed reply.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaChatResponse {
+    pub model: String,
+    pub created_at: String,
+    pub message: OllamaMessage,
+    pub done: bool,
+    pub done_reason: Option<String>,
+    pub prompt_eval_count: Option<i64>,
+    pub eval_count: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    pub role: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OllamaToolCall>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaToolCall {
+    pub function: OllamaFunctionCall,
+}
+
+// Unlike OpenAI, Ollama sends tool arguments as a JSON object, not a string.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaFunctionCall {
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl From<OllamaChatResponse> for ChatCompletionResponse {
+    fn from(response: OllamaChatResponse) -> Self {
+        let prompt_tokens = response.prompt_eval_count.unwrap_or_default();
+        let completion_tokens = response.eval_count.unwrap_or_default();
+
+        // Ollama does not identify tool calls, so each one gets a fresh id.
+        let tool_calls = response.message.tool_calls.map(|tool_calls| {
+            tool_calls
+                .into_iter()
+                .map(|call| ChatToolCall {
+                    id: format!("call_{}", Uuid::new_v4().simple()),
+                    kind: "function".to_string(),
+                    function: ChatFunctionCall {
+                        name: call.function.name,
+                        arguments: call.function.arguments.to_string(),
+                    },
+                })
+                .collect()
+        });
+
+        Self {
+            object: "chat.completion".to_string(),
+            model: response.model,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatMessage {
+                    role: response.message.role,
+                    content: response.message.content,
+                    tool_calls,
+                    ..Default::default()
+                },
+                finish_reason: response.done_reason,
+            }],
+            usage: Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+// A line of a streamed Ollama reply. The last one, marked `done`, carries the
+// usage of the call.
+impl From<OllamaChatResponse> for ChatCompletionChunk {
+    fn from(response: OllamaChatResponse) -> Self {
+        let usage = response.done.then(|| {
+            let prompt_tokens = response.prompt_eval_count.unwrap_or_default();
+            let completion_tokens = response.eval_count.unwrap_or_default();
+
+            Usage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            }
+        });
+
+        Self {
+            object: "chat.completion.chunk".to_string(),
+            model: response.model,
+            choices: vec![ChatCompletionChunkChoice {
+                index: 0,
+                delta: ChatMessageDelta {
+                    role: Some(response.message.role),
+                    content: Some(response.message.content),
+                },
+                finish_reason: response.done_reason,
+            }],
+            usage,
+            ..Default::default()
+        }
+    }
+}
@@ -1,9 +1,20 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
+
+use uuid::Uuid;
 
 use crate::{
     backend::{core::AgentBackend, openai::OpenAIBackend},
-    database::memory::MemoryEngine,
-    sdk::agent::Agent,
+    database::{memory::MemoryEngine, store::AgentRecord},
+    error::MyselfError,
+    llm::LLMConfiguration,
+    sdk::{
+        agent::{Agent, DefaultInteraction, SummarizationPolicy, DEFAULT_MAX_TOOL_ITERATIONS},
+        quota::Quota,
+        recall::{MemoryStrategy, SummaryWindow},
+        template::PromptTemplate,
+        tool::{Tool, Toolbox},
+        usage::PriceTable,
+    },
 };
 
 pub struct AgentBuilder<Backend>
@@ -14,6 +25,14 @@ where
     default_user_name: String,
     default_constitution: String,
     default_memory_size: usize,
+    summarization: Option<SummarizationPolicy>,
+    memory_strategy: Arc<dyn MemoryStrategy<Backend>>,
+    prompt_template: PromptTemplate,
+    tools: Toolbox,
+    max_tool_iterations: usize,
+    llm_configuration: Option<LLMConfiguration>,
+    quota: Quota,
+    prices: PriceTable,
     backend: PhantomData<Backend>,
 }
 impl<Backend> Default for AgentBuilder<Backend>
@@ -39,6 +58,14 @@ where
                 .unwrap_or("10".to_string())
                 .parse()
                 .unwrap_or(10),
+            summarization: None,
+            memory_strategy: Arc::new(SummaryWindow),
+            prompt_template: PromptTemplate::default(),
+            tools: Toolbox::new(),
+            max_tool_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
+            llm_configuration: None,
+            quota: Quota::default(),
+            prices: PriceTable::default(),
             backend: PhantomData,
         }
     }
@@ -63,38 +90,187 @@ where
         self
     }
 
+    pub fn summarization(&mut self, policy: SummarizationPolicy) -> &mut Self {
+        self.summarization = Some(policy);
+        self
+    }
+
+    pub fn memory_strategy(
+        &mut self,
+        strategy: impl MemoryStrategy<Backend> + 'static,
+    ) -> &mut Self {
+        self.memory_strategy = Arc::new(strategy);
+        self
+    }
+
+    pub fn prompt_template(&mut self, template: PromptTemplate) -> &mut Self {
+        self.prompt_template = template;
+        self
+    }
+
+    pub fn tool(&mut self, tool: impl Tool + 'static) -> &mut Self {
+        self.tools.register(tool);
+        self
+    }
+
+    pub fn max_tool_iterations(&mut self, max_tool_iterations: usize) -> &mut Self {
+        self.max_tool_iterations = max_tool_iterations;
+        self
+    }
+
+    pub fn llm_configuration(&mut self, configuration: LLMConfiguration) -> &mut Self {
+        self.llm_configuration = Some(configuration);
+        self
+    }
+
+    pub fn quota(&mut self, quota: Quota) -> &mut Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn prices(&mut self, prices: PriceTable) -> &mut Self {
+        self.prices = prices;
+        self
+    }
+
     pub async fn build(
         &mut self,
         llm_engine: Backend,
         mut memory_engine: MemoryEngine<Backend>,
-    ) -> Agent<Backend> {
-        memory_engine
+    ) -> Result<Agent<Backend>, MyselfError> {
+        self.validate(&self.quota)?;
+
+        if let Some(configuration) = &self.llm_configuration {
+            configuration.validate()?;
+        }
+
+        let agent = memory_engine
             .new_agent(
                 self.agent_name.to_owned(),
-                self.default_user_name.to_owned(),
-                self.default_constitution.to_owned(),
-                self.default_memory_size,
+                DefaultInteraction {
+                    user_name: self.default_user_name.to_owned(),
+                    constitution: self.default_constitution.to_owned(),
+                    memory_size: self.default_memory_size,
+                },
+                self.llm_configuration.clone(),
+                self.quota.clone(),
                 llm_engine,
                 memory_engine.clone(),
             )
-            .await
+            .await?;
+
+        Ok(self.configure(agent))
+    }
+
+    // Rehydrates the stored agent `id`. Its name, defaults, LLM configuration
+    // and quota come from the database and the builder's are ignored; the
+    // settings that are not stored (tools, memory strategy, prompt template,
+    // summarization, prices) are taken from the builder.
+    pub async fn load(
+        &mut self,
+        id: Uuid,
+        llm_engine: Backend,
+        memory_engine: MemoryEngine<Backend>,
+    ) -> Result<Agent<Backend>, MyselfError> {
+        let record = memory_engine
+            .get_agent(id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("agent {}", id)))?;
+
+        self.rehydrate(record, llm_engine, memory_engine)
+    }
+
+    // Loads the oldest agent with the builder's name, like `load`, or builds
+    // a new one when there is none.
+    pub async fn get_or_create_by_name(
+        &mut self,
+        llm_engine: Backend,
+        memory_engine: MemoryEngine<Backend>,
+    ) -> Result<Agent<Backend>, MyselfError> {
+        match memory_engine.get_agent_by_name(&self.agent_name).await? {
+            Some(record) => self.rehydrate(record, llm_engine, memory_engine),
+            None => self.build(llm_engine, memory_engine).await,
+        }
+    }
+
+    fn rehydrate(
+        &self,
+        record: AgentRecord,
+        llm_engine: Backend,
+        memory_engine: MemoryEngine<Backend>,
+    ) -> Result<Agent<Backend>, MyselfError> {
+        self.validate(&record.quota)?;
+
+        Ok(self.configure(MemoryEngine::agent_from_record(
+            record,
+            llm_engine,
+            memory_engine,
+        )))
+    }
+
+    fn validate(&self, quota: &Quota) -> Result<(), MyselfError> {
+        if let Some(policy) = &self.summarization {
+            policy.validate()?;
+        }
+
+        self.memory_strategy.validate()?;
+
+        self.prompt_template.validate()?;
+
+        self.tools.validate()?;
+
+        quota.validate()?;
+
+        if quota.max_cost.is_some() && self.prices.is_empty() {
+            return Err(MyselfError::Configuration(
+                "a max_cost quota needs a price table".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Applies the settings that are not stored with the agent.
+    fn configure(&self, mut agent: Agent<Backend>) -> Agent<Backend> {
+        agent.summarization = self.summarization.clone();
+        agent.memory_strategy = self.memory_strategy.clone();
+        agent.prompt_template = self.prompt_template.clone();
+        agent.tools = self.tools.clone();
+        agent.max_tool_iterations = self.max_tool_iterations;
+        agent.prices = self.prices.clone();
+
+        agent
     }
 }
 
+async fn default_engines() -> Result<(OpenAIBackend, MemoryEngine<OpenAIBackend>), MyselfError> {
+    let database_url = std::env::var("DATABASE_URL")
+        .map_err(|_| MyselfError::Configuration("DATABASE_URL is not set".to_string()))?;
+
+    Ok((
+        OpenAIBackend::new_defaults()?,
+        MemoryEngine::new(database_url).await?,
+    ))
+}
+
 impl AgentBuilder<OpenAIBackend> {
-    pub async fn build_default(&mut self) -> Agent<OpenAIBackend> {
-        let llm_engine = OpenAIBackend::new(std::env::var("OPENAI_API_KEY").unwrap());
-        let mut memory_engine = MemoryEngine::new(std::env::var("DATABASE_URL").unwrap()).await;
+    pub async fn build_default(&mut self) -> Result<Agent<OpenAIBackend>, MyselfError> {
+        let (llm_engine, memory_engine) = default_engines().await?;
 
-        memory_engine
-            .new_agent(
-                self.agent_name.to_owned(),
-                self.default_user_name.to_owned(),
-                self.default_constitution.to_owned(),
-                self.default_memory_size,
-                llm_engine,
-                memory_engine.clone(),
-            )
-            .await
+        self.build(llm_engine, memory_engine).await
+    }
+
+    pub async fn load_default(&mut self, id: Uuid) -> Result<Agent<OpenAIBackend>, MyselfError> {
+        let (llm_engine, memory_engine) = default_engines().await?;
+
+        self.load(id, llm_engine, memory_engine).await
+    }
+
+    pub async fn get_or_create_default_by_name(
+        &mut self,
+    ) -> Result<Agent<OpenAIBackend>, MyselfError> {
+        let (llm_engine, memory_engine) = default_engines().await?;
+
+        self.get_or_create_by_name(llm_engine, memory_engine).await
     }
 }
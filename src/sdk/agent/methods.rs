@@ -1,13 +1,23 @@
+use async_stream::try_stream;
+use futures::StreamExt;
 use uuid::Uuid;
 
-use crate::backend::core::AgentBackend;
-use crate::database::memory::MemoryEngine;
+use crate::backend::core::{AgentBackend, ResponseDelta, ResponseStream};
+use crate::error::MyselfError;
+use crate::llm::{LLMEngineError, LLMOverrides};
 
 use crate::sdk::interaction::Interaction;
 use crate::sdk::interaction::InteractionBlock;
+use crate::sdk::interaction::InteractionBlockRole;
 use crate::sdk::interaction::InteractionState;
+use crate::sdk::interaction::InteractionsQuery;
+use crate::sdk::interaction::Meta;
 use crate::sdk::interaction::WithAgent;
 use crate::sdk::interaction::WithoutAgent;
+use crate::sdk::participant::Participant;
+use crate::sdk::quota::{Allowance, Quota, QuotaScope};
+use crate::sdk::template::PromptTemplate;
+use crate::sdk::usage::UsageReport;
 
 use super::structure::Agent;
 
@@ -18,84 +28,400 @@ where
     pub async fn forgot_short_term_memory(
         &mut self,
         interaction_id: Uuid,
-    ) -> Option<Interaction<Backend>> {
-        let interaction = self
-            .get_memory_engine()
-            .as_mut()
-            .unwrap()
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
             .get_interaction(interaction_id)
-            .await;
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        memory_engine
+            .set_short_term_memory(interaction.id, "".to_string())
+            .await
+    }
 
-        match interaction {
-            Some(interaction) => {
-                let mut database_core = self.get_memory_engine().as_mut().unwrap().to_owned();
+    // The message is attributed to the interaction's participant, if any.
+    pub async fn interact(
+        &mut self,
+        interaction_id: Uuid,
+        message: &String,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.interact_from(interaction_id, None, message).await
+    }
 
-                Some(
-                    database_core
-                        .set_short_term_memory(interaction.id, "".to_string())
-                        .await,
-                )
+    // Like `interact`, with the message written by `participant_id`.
+    pub async fn interact_as(
+        &mut self,
+        interaction_id: Uuid,
+        participant_id: Uuid,
+        message: &String,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.interact_from(interaction_id, Some(participant_id), message)
+            .await
+    }
+
+    // The user block for `message`, named after and attributed to `author`,
+    // or else to the interaction's user and participant.
+    async fn user_block(
+        &mut self,
+        interaction: &Interaction<Backend>,
+        author: Option<Uuid>,
+        message: String,
+    ) -> Result<InteractionBlock, MyselfError> {
+        let (name, participant_id) = match author {
+            Some(participant_id) => {
+                let participant = self
+                    .memory_engine()?
+                    .get_participant(participant_id)
+                    .await?
+                    .ok_or_else(|| {
+                        MyselfError::NotFound(format!("participant {}", participant_id))
+                    })?;
+
+                (participant.display_name, Some(participant.id))
             }
-            None => None,
-        }
+            None => (interaction.user_name.clone(), interaction.participant_id),
+        };
+
+        Ok(InteractionBlock {
+            participant_id,
+            ..InteractionBlock::new_user(interaction.id, message, Some(name))
+        })
     }
 
-    pub async fn interact(
+    async fn interact_from(
         &mut self,
         interaction_id: Uuid,
+        author: Option<Uuid>,
         message: &String,
-    ) -> Option<(InteractionBlock, InteractionBlock)> {
-        let interaction = self
-            .get_memory_engine()
-            .as_mut()
-            .unwrap()
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
             .get_interaction(interaction_id)
-            .await;
-
-        let interaction_in = InteractionBlock::new_agent(
-            interaction_id,
-            message.to_owned(),
-            Some(self.my_name.to_owned()),
-        );
-
-        match interaction {
-            Some(interaction) => {
-                let mut memory_engine = self.get_memory_engine().as_mut().unwrap().to_owned();
-
-                let interaction_out = self
-                    .get_backend()
-                    .clone()
-                    .unwrap()
-                    .predict_response(
-                        interaction.clone().with_agent(self.clone()),
-                        &interaction_in,
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        let input = self
+            .user_block(&interaction, author, message.to_owned())
+            .await?;
+
+        self.complete_turn(&interaction, input, true).await
+    }
+
+    // Answers the latest block of the interaction, which is already stored,
+    // such as a message another agent wrote. Returns the agent's response.
+    pub async fn respond(&mut self, interaction_id: Uuid) -> Result<InteractionBlock, MyselfError> {
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        let input = memory_engine
+            .get_interaction_long_term_memory(interaction_id, 1)
+            .await?
+            .pop()
+            .ok_or_else(|| {
+                MyselfError::Configuration(format!(
+                    "interaction {} has nothing to respond to",
+                    interaction_id
+                ))
+            })?;
+
+        let (_, interaction_out) = self.complete_turn(&interaction, input, false).await?;
+
+        Ok(interaction_out)
+    }
+
+    // Calls the model, and the tools it asks for, until it answers `input`.
+    // The turn is appended to long-term memory step by step, so each quota
+    // check counts the calls made before it and a turn that fails keeps the
+    // tool calls it made. `input` itself is only appended when `store_input`
+    // is set, together with the first step. The interaction is summarized
+    // before the turn, so a failed summarization leaves nothing stored, and
+    // nothing is summarized or embedded once a quota is used up.
+    async fn complete_turn(
+        &mut self,
+        interaction: &Interaction<Backend>,
+        input: InteractionBlock,
+        store_input: bool,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        let mut memory_engine = self.memory_engine()?;
+        let interaction_id = interaction.id;
+
+        self.check_quotas(interaction).await?;
+
+        let interaction = &self
+            .summarize_interaction(interaction_id)
+            .await?
+            .unwrap_or_else(|| interaction.clone());
+
+        // Stores other than the in-memory one read blocks back without their
+        // embedding, which semantic recall needs.
+        let input = match input.embedding {
+            Some(_) => input,
+            None => self.embed_block(input).await?,
+        };
+
+        let mut interaction_in = (!store_input).then(|| input.clone());
+        let mut turn = vec![input];
+
+        let mut backend = self.backend()?;
+        let mut iterations = 0;
+
+        let interaction_out = loop {
+            self.check_quotas(interaction).await?;
+
+            let (tool_calls, responses): (Vec<_>, Vec<_>) = backend
+                .predict_step(interaction.clone().with_agent(self.clone()), &turn)
+                .await?
+                .into_iter()
+                .map(|block| InteractionBlock {
+                    agent_id: Some(self.id),
+                    ..block
+                })
+                .partition(|block| matches!(block.role, InteractionBlockRole::ToolCall));
+
+            if tool_calls.is_empty() {
+                let response = responses.into_iter().last().ok_or_else(|| {
+                    LLMEngineError::InvalidResponse("backend returned an empty step".to_string())
+                })?;
+
+                break self.embed_block(response).await?;
+            }
+
+            let exceeded = iterations == self.max_tool_iterations;
+
+            let mut results = Vec::with_capacity(tool_calls.len());
+
+            for call in &tool_calls {
+                let tool_name = call.name.clone().unwrap_or_default();
+
+                // Calls past the limit are answered without being run, so the
+                // stored calls are never left without a result.
+                let result = if exceeded {
+                    format!("error: tool call limit of {} reached", iterations)
+                } else {
+                    self.tools.call(&tool_name, &call.content).await
+                };
+
+                results.push(InteractionBlock {
+                    agent_id: Some(self.id),
+                    ..InteractionBlock::new_tool_result(
+                        interaction_id,
+                        tool_name,
+                        result,
+                        call.tool_call_id.clone().unwrap_or_default(),
                     )
-                    .await;
+                });
+            }
+
+            if interaction_in.is_none() {
+                interaction_in = Some(
+                    memory_engine
+                        .append_to_long_term_memory(interaction_id, &turn[0])
+                        .await?,
+                );
+            }
+
+            for block in tool_calls.iter().chain(&results) {
+                memory_engine
+                    .append_to_long_term_memory(interaction_id, block)
+                    .await?;
+            }
+
+            if exceeded {
+                return Err(MyselfError::ToolIterationsExceeded(iterations));
+            }
 
-                let interaction_in = memory_engine
-                    .append_to_long_term_memory(interaction_id, &interaction_in)
-                    .await;
+            iterations += 1;
 
-                let interaction_out = memory_engine
-                    .append_to_long_term_memory(interaction_id, &interaction_out)
-                    .await;
+            turn.extend(tool_calls);
+            turn.extend(results);
+        };
 
-                Some((interaction_in, interaction_out))
+        let interaction_in = match interaction_in {
+            Some(block) => block,
+            None => {
+                memory_engine
+                    .append_to_long_term_memory(interaction_id, &turn[0])
+                    .await?
             }
-            None => None,
+        };
+
+        let interaction_out = memory_engine
+            .append_to_long_term_memory(interaction_id, &interaction_out)
+            .await?;
+
+        Ok((interaction_in, interaction_out))
+    }
+
+    // Applies the agent's summarization policy, returning the updated
+    // interaction if older blocks were condensed into its short-term memory.
+    pub async fn summarize_interaction(
+        &mut self,
+        interaction_id: Uuid,
+    ) -> Result<Option<Interaction<Backend>>, MyselfError> {
+        let Some(policy) = self.summarization.clone() else {
+            return Ok(None);
+        };
+
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        let pending = memory_engine
+            .get_interaction_blocks_after(
+                interaction_id,
+                interaction.summarized_until,
+                policy.threshold + 1,
+            )
+            .await?;
+
+        if pending.len() <= policy.threshold {
+            return Ok(None);
         }
+
+        let condensed = &pending[..pending.len().saturating_sub(policy.keep_recent)];
+
+        let Some(last) = condensed.last() else {
+            return Ok(None);
+        };
+
+        let (summary, usage) = self
+            .backend()?
+            .summarize(interaction.clone().with_agent(self.clone()), condensed)
+            .await?;
+
+        if let Some(usage) = usage {
+            memory_engine
+                .append_summary_usage(interaction_id, self.id, &usage)
+                .await?;
+        }
+
+        memory_engine
+            .set_summary(interaction_id, summary, last.cursor())
+            .await
+            .map(Some)
+    }
+
+    // Yields the response as it is generated. Both blocks are appended to
+    // long-term memory once the stream has been consumed to the end. As with
+    // `interact`, the interaction is summarized before the model is called.
+    pub async fn interact_stream(
+        &mut self,
+        interaction_id: Uuid,
+        message: &String,
+    ) -> Result<ResponseStream, MyselfError>
+    where
+        Backend: 'static,
+    {
+        self.interact_stream_from(interaction_id, None, message)
+            .await
+    }
+
+    pub async fn interact_stream_as(
+        &mut self,
+        interaction_id: Uuid,
+        participant_id: Uuid,
+        message: &String,
+    ) -> Result<ResponseStream, MyselfError>
+    where
+        Backend: 'static,
+    {
+        self.interact_stream_from(interaction_id, Some(participant_id), message)
+            .await
+    }
+
+    async fn interact_stream_from(
+        &mut self,
+        interaction_id: Uuid,
+        author: Option<Uuid>,
+        message: &String,
+    ) -> Result<ResponseStream, MyselfError>
+    where
+        Backend: 'static,
+    {
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        self.check_quotas(&interaction).await?;
+
+        let interaction = self
+            .summarize_interaction(interaction_id)
+            .await?
+            .unwrap_or(interaction);
+
+        let interaction_in = self
+            .user_block(&interaction, author, message.to_owned())
+            .await?;
+        let interaction_in = self.embed_block(interaction_in).await?;
+
+        self.check_quotas(&interaction).await?;
+
+        let mut deltas = self
+            .backend()?
+            .predict_response_stream(
+                interaction.clone().with_agent(self.clone()),
+                &interaction_in,
+            )
+            .await?;
+
+        let mut agent = self.clone();
+
+        Ok(Box::pin(try_stream! {
+            let mut response = String::new();
+            let mut usage = None;
+
+            while let Some(delta) = deltas.next().await {
+                match delta? {
+                    ResponseDelta::Text(text) => {
+                        response.push_str(&text);
+                        yield text;
+                    }
+                    ResponseDelta::Usage(reported) => usage = Some(reported),
+                }
+            }
+
+            let interaction_out = agent
+                .embed_block(InteractionBlock {
+                    agent_id: Some(agent.id),
+                    usage,
+                    ..InteractionBlock::new_agent(
+                        interaction_id,
+                        response.trim().to_string(),
+                        Some(agent.my_name.clone()),
+                    )
+                })
+                .await?;
+
+            memory_engine
+                .append_to_long_term_memory(interaction_id, &interaction_in)
+                .await?;
+
+            memory_engine
+                .append_to_long_term_memory(interaction_id, &interaction_out)
+                .await?;
+        }))
     }
 
     pub async fn interact_default(
         &mut self,
         message: &String,
-    ) -> Option<(InteractionBlock, InteractionBlock)> {
-        self.clone()
-            .get_memory_engine()
-            .as_mut()
-            .unwrap()
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.memory_engine()?
             .get_or_create_default_interaction(self)
-            .await
+            .await?
             .interact(message)
             .await
     }
@@ -104,7 +430,7 @@ where
         &mut self,
         interaction: Interaction<Backend, S>,
         message: &String,
-    ) -> Option<(InteractionBlock, InteractionBlock)> {
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
         self.interact(interaction.id, message).await
     }
 
@@ -113,23 +439,33 @@ where
         user_name: String,
         constitution: String,
         memory_size: usize,
-    ) -> Interaction<Backend, WithAgent> {
-        self.get_memory_engine()
-            .clone()
-            .as_mut()
-            .unwrap()
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        self.memory_engine()?
             .new_interaction_with_agent(user_name, constitution, memory_size, self)
             .await
     }
 
+    // A new interaction with `participant`, using the agent's default
+    // constitution and memory size.
+    pub async fn init_interaction_for(
+        &mut self,
+        participant: &Participant,
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        self.memory_engine()?
+            .new_participant_interaction(
+                participant,
+                self.default_interaction.constitution.clone(),
+                self.default_interaction.memory_size,
+                self,
+            )
+            .await
+    }
+
     pub async fn init_interaction_defaults(
         &mut self,
         new_user_name: Option<String>,
-    ) -> Interaction<Backend, WithAgent> {
-        self.get_memory_engine()
-            .clone()
-            .as_mut()
-            .unwrap()
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        self.memory_engine()?
             .new_interaction_with_agent(
                 new_user_name.unwrap_or(self.default_interaction.user_name.clone()),
                 self.default_interaction.constitution.clone(),
@@ -142,44 +478,161 @@ where
     pub async fn get_interaction(
         &mut self,
         interaction_id: Uuid,
-    ) -> Option<Interaction<Backend, WithoutAgent>> {
-        self.get_memory_engine()
-            .as_mut()
-            .unwrap()
-            .get_interaction(interaction_id)
-            .await
+    ) -> Result<Option<Interaction<Backend, WithoutAgent>>, MyselfError> {
+        self.memory_engine()?.get_interaction(interaction_id).await
     }
 
-    pub async fn get_all_interactions(&mut self) -> Vec<Interaction<Backend, WithoutAgent>> {
-        self.get_memory_engine()
-            .as_mut()
-            .unwrap()
-            .get_all_interactions()
+    pub async fn get_all_interactions(
+        &mut self,
+        options: InteractionsQuery,
+    ) -> Result<Vec<Interaction<Backend, WithoutAgent>>, MyselfError> {
+        self.memory_engine()?
+            .get_all_interactions(self.id, options)
             .await
     }
 
-    pub async fn get_default_interaction(&mut self) -> Interaction<Backend, WithAgent> {
-        self.clone()
-            .get_memory_engine()
-            .as_mut()
-            .unwrap()
+    pub async fn get_default_interaction(
+        &mut self,
+    ) -> Result<Interaction<Backend, WithAgent>, MyselfError> {
+        self.memory_engine()?
             .get_or_create_default_interaction(self)
             .await
     }
 
+    // Makes one of the agent's interactions the one `interact_default` uses.
+    pub async fn set_default_interaction(
+        &mut self,
+        interaction_id: Uuid,
+    ) -> Result<Meta, MyselfError> {
+        self.memory_engine()?
+            .set_default_interaction(self.id, interaction_id)
+            .await
+    }
+
+    pub async fn set_llm_overrides(
+        &mut self,
+        interaction_id: Uuid,
+        overrides: LLMOverrides,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.memory_engine()?
+            .set_llm_overrides(interaction_id, overrides)
+            .await
+    }
+
+    pub async fn set_interaction_quota(
+        &mut self,
+        interaction_id: Uuid,
+        quota: Quota,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        if quota.max_cost.is_some() && self.prices.is_empty() {
+            return Err(MyselfError::Configuration(
+                "a max_cost quota needs a price table".to_string(),
+            ));
+        }
+
+        self.memory_engine()?.set_quota(interaction_id, quota).await
+    }
+
+    // `None` makes the interaction use the agent's template again.
+    pub async fn set_interaction_prompt_template(
+        &mut self,
+        interaction_id: Uuid,
+        template: Option<PromptTemplate>,
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.memory_engine()?
+            .set_prompt_template(interaction_id, template)
+            .await
+    }
+
+    // Token usage of the agent's responses in all interactions, including
+    // group interactions other agents started.
+    pub async fn usage(&mut self) -> Result<UsageReport, MyselfError> {
+        self.memory_engine()?.get_agent_usage(self.id, None).await
+    }
+
+    pub async fn get_interaction_usage(
+        &mut self,
+        interaction_id: Uuid,
+    ) -> Result<UsageReport, MyselfError> {
+        self.memory_engine()?
+            .get_interaction_usage(interaction_id, None)
+            .await
+    }
+
+    // What is left of the agent's own quota.
+    pub async fn allowance(&mut self) -> Result<Allowance, MyselfError> {
+        self.memory_engine()?
+            .get_allowance(QuotaScope::Agent, self.id, &self.quota, &self.prices)
+            .await
+    }
+
+    // What is left of the interaction's quota. The agent's quota applies too.
+    pub async fn get_interaction_allowance(
+        &mut self,
+        interaction_id: Uuid,
+    ) -> Result<Allowance, MyselfError> {
+        let mut memory_engine = self.memory_engine()?;
+
+        let interaction = memory_engine
+            .get_interaction(interaction_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("interaction {}", interaction_id)))?;
+
+        memory_engine
+            .get_allowance(
+                QuotaScope::Interaction,
+                interaction_id,
+                &interaction.quota,
+                &self.prices,
+            )
+            .await
+    }
+
+    // Embeds the block when the agent's memory strategy uses embeddings.
+    async fn embed_block(
+        &mut self,
+        mut block: InteractionBlock,
+    ) -> Result<InteractionBlock, MyselfError> {
+        if !self.memory_strategy.uses_embeddings() || block.content.trim().is_empty() {
+            return Ok(block);
+        }
+
+        block.embedding = self
+            .backend()?
+            .embed(std::slice::from_ref(&block.content))
+            .await?
+            .pop();
+
+        Ok(block)
+    }
+
+    async fn check_quotas(&self, interaction: &Interaction<Backend>) -> Result<(), MyselfError> {
+        let memory_engine = self.memory_engine()?;
+
+        for (scope, id, quota) in [
+            (QuotaScope::Agent, self.id, &self.quota),
+            (QuotaScope::Interaction, interaction.id, &interaction.quota),
+        ] {
+            if quota.is_unlimited() {
+                continue;
+            }
+
+            memory_engine
+                .get_allowance(scope, id, quota, &self.prices)
+                .await?
+                .check(quota)?;
+        }
+
+        Ok(())
+    }
+
     pub async fn update_long_term_memory(
         &mut self,
         interaction_id: Uuid,
         constitution: String,
-    ) -> Interaction<Backend> {
-        self.get_memory_engine()
-            .as_mut()
-            .unwrap()
+    ) -> Result<Interaction<Backend>, MyselfError> {
+        self.memory_engine()?
             .update_constitution(interaction_id, constitution)
             .await
     }
-
-    pub fn memory_engine(&mut self) -> Box<MemoryEngine<Backend>> {
-        self.get_memory_engine().unwrap()
-    }
 }
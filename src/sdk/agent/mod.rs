@@ -3,4 +3,4 @@ mod methods;
 mod structure;
 
 pub use builder::AgentBuilder;
-pub use structure::{Agent, DefaultInteraction};
+pub use structure::{Agent, DefaultInteraction, SummarizationPolicy, DEFAULT_MAX_TOOL_ITERATIONS};
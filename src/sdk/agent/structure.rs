@@ -1,5 +1,14 @@
 use crate::backend::core::AgentBackend;
 use crate::database::memory::MemoryEngine;
+use crate::error::MyselfError;
+use crate::llm::LLMConfiguration;
+use crate::sdk::quota::Quota;
+use crate::sdk::recall::{MemoryStrategy, SummaryWindow};
+use crate::sdk::template::PromptTemplate;
+use crate::sdk::tool::Toolbox;
+use crate::sdk::usage::PriceTable;
+
+use std::sync::Arc;
 
 use uuid::Uuid;
 
@@ -10,6 +19,37 @@ pub struct DefaultInteraction {
     pub memory_size: usize,
 }
 
+pub const DEFAULT_MAX_TOOL_ITERATIONS: usize = 8;
+
+// Once more than `threshold` blocks of an interaction have not been summarized,
+// all but the `keep_recent` newest of them are condensed into its short-term
+// memory before the agent's next turn.
+#[derive(Clone, Debug)]
+pub struct SummarizationPolicy {
+    pub threshold: usize,
+    pub keep_recent: usize,
+}
+
+impl SummarizationPolicy {
+    pub fn new(threshold: usize, keep_recent: usize) -> Self {
+        Self {
+            threshold,
+            keep_recent,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        if self.keep_recent >= self.threshold {
+            return Err(MyselfError::Configuration(format!(
+                "summarization threshold ({}) must be greater than keep_recent ({})",
+                self.threshold, self.keep_recent
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Agent<Backend>
 where
@@ -18,6 +58,19 @@ where
     pub id: Uuid,
     pub my_name: String,
     pub default_interaction: DefaultInteraction,
+    pub summarization: Option<SummarizationPolicy>,
+    // Chooses what each prompt remembers of the interaction.
+    pub memory_strategy: Arc<dyn MemoryStrategy<Backend>>,
+    // Lays out the system messages of the agent's prompts.
+    pub prompt_template: PromptTemplate,
+    pub tools: Toolbox,
+    pub max_tool_iterations: usize,
+    // Overrides the backend's own configuration when set.
+    pub llm_configuration: Option<LLMConfiguration>,
+    // Enforced across all the agent's interactions before each model call.
+    pub quota: Quota,
+    // Used to price usage towards spend limits.
+    pub prices: PriceTable,
 
     backend: Option<Box<Backend>>,
     memory_engine: Option<Box<MemoryEngine<Backend>>>,
@@ -34,6 +87,16 @@ where
     pub fn get_backend(&self) -> Option<Box<Backend>> {
         self.backend.clone()
     }
+
+    pub fn memory_engine(&self) -> Result<Box<MemoryEngine<Backend>>, MyselfError> {
+        self.get_memory_engine()
+            .ok_or_else(|| MyselfError::Configuration("agent has no memory engine".to_string()))
+    }
+
+    pub fn backend(&self) -> Result<Box<Backend>, MyselfError> {
+        self.get_backend()
+            .ok_or_else(|| MyselfError::Configuration("agent has no backend".to_string()))
+    }
 }
 
 impl<Backend> Agent<Backend>
@@ -51,6 +114,14 @@ where
             id,
             my_name,
             default_interaction,
+            summarization: None,
+            memory_strategy: Arc::new(SummaryWindow),
+            prompt_template: PromptTemplate::default(),
+            tools: Toolbox::new(),
+            max_tool_iterations: DEFAULT_MAX_TOOL_ITERATIONS,
+            llm_configuration: None,
+            quota: Quota::default(),
+            prices: PriceTable::default(),
             backend: Some(Box::new(llm_engine)),
             memory_engine: Some(Box::new(memory_engine)),
         }
@@ -0,0 +1,530 @@
+use std::{
+    cmp::Reverse,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    backend::core::AgentBackend,
+    database::memory::MemoryEngine,
+    error::MyselfError,
+    llm::LLMEngineError,
+    sdk::{
+        agent::Agent,
+        interaction::{InteractionBlock, InteractionBlockRole},
+        participant::Participant,
+    },
+};
+
+// How many recent blocks speaker selectors get to look at.
+pub(crate) const SPEAKER_HISTORY_SIZE: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Member {
+    Participant(Uuid),
+    Agent(Uuid),
+}
+
+impl Member {
+    pub fn participant_id(&self) -> Option<Uuid> {
+        match self {
+            Member::Participant(id) => Some(*id),
+            Member::Agent(_) => None,
+        }
+    }
+
+    pub fn agent_id(&self) -> Option<Uuid> {
+        match self {
+            Member::Agent(id) => Some(*id),
+            Member::Participant(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Member::Participant(id) => write!(f, "participant {}", id),
+            Member::Agent(id) => write!(f, "agent {}", id),
+        }
+    }
+}
+
+// A participant or agent taking part in a group interaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InteractionMember {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+
+    pub interaction_id: Uuid,
+    pub member: Member,
+}
+
+impl InteractionMember {
+    pub fn new(interaction_id: Uuid, member: Member) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            interaction_id,
+            member,
+        }
+    }
+}
+
+// What a speaker selector chooses from.
+#[derive(Clone, Debug)]
+pub struct SpeakerContext<'a> {
+    // The names of the agents that can answer, in the order they joined.
+    pub agent_names: &'a [String],
+    pub input: &'a InteractionBlock,
+    // The most recent blocks of the interaction, oldest first.
+    pub history: &'a [InteractionBlock],
+}
+
+#[async_trait]
+pub trait SpeakerSelector
+where
+    Self: Debug + Send + Sync,
+{
+    // The index in `agent_names` of the agent that answers the input.
+    async fn select(&self, context: &SpeakerContext<'_>) -> Result<usize, MyselfError>;
+}
+
+// The agent addressed as `@name` in the input, or else the agent that spoke
+// last, or else the first agent to join. Group chats use this unless
+// configured otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mentioned;
+
+// The position of the first occurrence of `word` in `text` that is not part
+// of a longer word.
+fn word_position(text: &str, word: &str) -> Option<usize> {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+    text.match_indices(word).find_map(|(position, _)| {
+        let starts_word = text[..position]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !is_word_char(c))
+            || word.starts_with(|c: char| !is_word_char(c));
+        let ends_word = text[position + word.len()..]
+            .chars()
+            .next()
+            .is_none_or(|c| !is_word_char(c));
+
+        (starts_word && ends_word).then_some(position)
+    })
+}
+
+// The index of the name in `names` that appears first in `text`, ignoring
+// case, with `prefix` in front of it.
+fn first_named(text: &str, names: &[String], prefix: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(index, name)| {
+            word_position(&text, &format!("{}{}", prefix, name.to_lowercase()))
+                .map(|position| (position, Reverse(name.len()), index))
+        })
+        .min()
+        .map(|(_, _, index)| index)
+}
+
+// The index of the agent that wrote the latest agent block.
+fn last_speaker(context: &SpeakerContext<'_>) -> Option<usize> {
+    std::iter::once(context.input)
+        .chain(context.history.iter().rev())
+        .filter(|block| matches!(block.role, InteractionBlockRole::Agent))
+        .find_map(|block| {
+            context
+                .agent_names
+                .iter()
+                .position(|name| Some(name) == block.name.as_ref())
+        })
+}
+
+#[async_trait]
+impl SpeakerSelector for Mentioned {
+    async fn select(&self, context: &SpeakerContext<'_>) -> Result<usize, MyselfError> {
+        Ok(
+            first_named(&context.input.content, context.agent_names, "@")
+                .or_else(|| last_speaker(context))
+                .unwrap_or(0),
+        )
+    }
+}
+
+// Each agent in turn, starting with the one after the agent that spoke last.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RoundRobin;
+
+#[async_trait]
+impl SpeakerSelector for RoundRobin {
+    async fn select(&self, context: &SpeakerContext<'_>) -> Result<usize, MyselfError> {
+        Ok(last_speaker(context).map_or(0, |index| (index + 1) % context.agent_names.len().max(1)))
+    }
+}
+
+// Asks `agent` who should speak next, showing it the recent conversation. The
+// questions are asked in an interaction of the moderator's own, created on
+// first use, so its choices and their usage are kept.
+pub struct Moderator<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    agent: Agent<Backend>,
+    interaction_id: Mutex<Option<Uuid>>,
+}
+
+impl<Backend> Moderator<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    pub fn new(agent: Agent<Backend>) -> Self {
+        Self {
+            agent,
+            interaction_id: Mutex::new(None),
+        }
+    }
+
+    // The moderator's own interaction, once it has been asked anything.
+    pub fn interaction_id(&self) -> Option<Uuid> {
+        *self
+            .interaction_id
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<Backend> Debug for Moderator<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Moderator")
+            .field("agent", &self.agent.my_name)
+            .field("interaction_id", &self.interaction_id())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<Backend> SpeakerSelector for Moderator<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn select(&self, context: &SpeakerContext<'_>) -> Result<usize, MyselfError> {
+        let mut agent = self.agent.clone();
+
+        let interaction_id = match self.interaction_id() {
+            Some(interaction_id) => interaction_id,
+            None => {
+                let interaction_id = agent.init_interaction_defaults(None).await?.id;
+
+                *self
+                    .interaction_id
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(interaction_id);
+
+                interaction_id
+            }
+        };
+
+        let mut blocks = context.history.to_vec();
+
+        if blocks.iter().all(|block| block.id != context.input.id) {
+            blocks.push(context.input.clone());
+        }
+
+        let question = format!(
+            "Conversation so far:\n{}\n\nWho should speak next? Answer with just one of these names: {}.",
+            agent.prompt_template.transcript(&blocks)?,
+            context.agent_names.join(", ")
+        );
+
+        let (_, answer) = agent.interact(interaction_id, &question).await?;
+
+        first_named(&answer.content, context.agent_names, "").ok_or_else(|| {
+            LLMEngineError::InvalidResponse(format!(
+                "moderator chose none of {}: {:?}",
+                context.agent_names.join(", "),
+                answer.content
+            ))
+            .into()
+        })
+    }
+}
+
+// An interaction shared by several participants and agents. Each message is
+// sent by a participant and answered by the agent its speaker selector picks,
+// and only members of the interaction can send or answer.
+#[derive(Clone, Debug)]
+pub struct GroupChat<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    pub interaction_id: Uuid,
+
+    agents: Vec<Agent<Backend>>,
+    selector: Arc<dyn SpeakerSelector>,
+    memory_engine: MemoryEngine<Backend>,
+}
+
+impl<Backend> GroupChat<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    // A new group interaction between `agents` and `participants`. It belongs
+    // to the first agent and uses its default memory size, and its user name
+    // lists the participants.
+    pub async fn start(
+        agents: Vec<Agent<Backend>>,
+        participants: &[Participant],
+        constitution: String,
+    ) -> Result<Self, MyselfError> {
+        let host = agents.first().ok_or_else(|| {
+            MyselfError::Configuration("group chat needs at least one agent".to_string())
+        })?;
+
+        let mut memory_engine = *host.memory_engine()?;
+
+        let user_name = if participants.is_empty() {
+            host.default_interaction.user_name.clone()
+        } else {
+            participants
+                .iter()
+                .map(|participant| participant.display_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let interaction = memory_engine
+            .new_interaction_with_agent(
+                user_name,
+                constitution,
+                host.default_interaction.memory_size,
+                host,
+            )
+            .await?;
+
+        let members = agents.iter().map(|agent| Member::Agent(agent.id)).chain(
+            participants
+                .iter()
+                .map(|participant| Member::Participant(participant.id)),
+        );
+
+        for member in members {
+            memory_engine
+                .add_interaction_member(interaction.id, member)
+                .await?;
+        }
+
+        Ok(Self {
+            interaction_id: interaction.id,
+            agents,
+            selector: Arc::new(Mentioned),
+            memory_engine,
+        })
+    }
+
+    // Resumes the group interaction `interaction_id`, answered by `agents`,
+    // which must all be members of it.
+    pub async fn open(
+        interaction_id: Uuid,
+        agents: Vec<Agent<Backend>>,
+    ) -> Result<Self, MyselfError> {
+        let memory_engine = *agents
+            .first()
+            .ok_or_else(|| {
+                MyselfError::Configuration("group chat needs at least one agent".to_string())
+            })?
+            .memory_engine()?;
+
+        let members = memory_engine
+            .get_interaction_members(interaction_id)
+            .await?;
+
+        if members.is_empty() {
+            return Err(MyselfError::NotFound(format!(
+                "group interaction {}",
+                interaction_id
+            )));
+        }
+
+        for agent in &agents {
+            if !members
+                .iter()
+                .any(|member| member.member == Member::Agent(agent.id))
+            {
+                return Err(MyselfError::Configuration(format!(
+                    "agent {} is not a member of interaction {}",
+                    agent.id, interaction_id
+                )));
+            }
+        }
+
+        Ok(Self {
+            interaction_id,
+            agents,
+            selector: Arc::new(Mentioned),
+            memory_engine,
+        })
+    }
+
+    pub fn selector(mut self, selector: impl SpeakerSelector + 'static) -> Self {
+        self.selector = Arc::new(selector);
+        self
+    }
+
+    pub fn agents(&self) -> &[Agent<Backend>] {
+        &self.agents
+    }
+
+    pub async fn members(&self) -> Result<Vec<InteractionMember>, MyselfError> {
+        self.memory_engine
+            .get_interaction_members(self.interaction_id)
+            .await
+    }
+
+    pub async fn add_participant(
+        &mut self,
+        participant_id: Uuid,
+    ) -> Result<InteractionMember, MyselfError> {
+        self.memory_engine
+            .add_interaction_member(self.interaction_id, Member::Participant(participant_id))
+            .await
+    }
+
+    pub async fn add_agent(
+        &mut self,
+        agent: Agent<Backend>,
+    ) -> Result<InteractionMember, MyselfError> {
+        let member = self
+            .memory_engine
+            .add_interaction_member(self.interaction_id, Member::Agent(agent.id))
+            .await?;
+
+        if !self.agents.iter().any(|other| other.id == agent.id) {
+            self.agents.push(agent);
+        }
+
+        Ok(member)
+    }
+
+    pub async fn remove(&mut self, member: Member) -> Result<(), MyselfError> {
+        self.memory_engine
+            .remove_interaction_member(self.interaction_id, member)
+            .await?;
+
+        self.agents
+            .retain(|agent| Member::Agent(agent.id) != member);
+
+        Ok(())
+    }
+
+    // Sends `message` from `participant_id`, answered by the agent the
+    // speaker selector picks.
+    pub async fn send(
+        &mut self,
+        participant_id: Uuid,
+        message: &String,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.check_member(Member::Participant(participant_id))
+            .await?;
+
+        let participant = self
+            .memory_engine
+            .get_participant(participant_id)
+            .await?
+            .ok_or_else(|| MyselfError::NotFound(format!("participant {}", participant_id)))?;
+
+        let input = InteractionBlock {
+            participant_id: Some(participant_id),
+            ..InteractionBlock::new_user(
+                self.interaction_id,
+                message.to_owned(),
+                Some(participant.display_name),
+            )
+        };
+
+        let history = self
+            .memory_engine
+            .get_interaction_long_term_memory(self.interaction_id, SPEAKER_HISTORY_SIZE)
+            .await?;
+
+        let agent_names = self
+            .agents
+            .iter()
+            .map(|agent| agent.my_name.clone())
+            .collect::<Vec<_>>();
+
+        let index = self
+            .selector
+            .select(&SpeakerContext {
+                agent_names: &agent_names,
+                input: &input,
+                history: &history,
+            })
+            .await?;
+
+        let agent = self.agents.get_mut(index).ok_or_else(|| {
+            MyselfError::Configuration(format!(
+                "speaker selector chose agent {} of {}",
+                index,
+                agent_names.len()
+            ))
+        })?;
+
+        agent
+            .interact_as(self.interaction_id, participant_id, message)
+            .await
+    }
+
+    // Sends `message` from `participant_id`, answered by `agent_id`.
+    pub async fn send_to(
+        &mut self,
+        agent_id: Uuid,
+        participant_id: Uuid,
+        message: &String,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.check_member(Member::Participant(participant_id))
+            .await?;
+
+        let agent = self
+            .agents
+            .iter_mut()
+            .find(|agent| agent.id == agent_id)
+            .ok_or_else(|| {
+                MyselfError::Configuration(format!(
+                    "agent {} is not a member of interaction {}",
+                    agent_id, self.interaction_id
+                ))
+            })?;
+
+        agent
+            .interact_as(self.interaction_id, participant_id, message)
+            .await
+    }
+
+    async fn check_member(&self, member: Member) -> Result<(), MyselfError> {
+        if !self
+            .members()
+            .await?
+            .iter()
+            .any(|existing| existing.member == member)
+        {
+            return Err(MyselfError::Configuration(format!(
+                "{} is not a member of interaction {}",
+                member, self.interaction_id
+            )));
+        }
+
+        Ok(())
+    }
+}
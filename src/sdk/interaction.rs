@@ -1,6 +1,13 @@
-use std::{marker::PhantomData, str::FromStr};
-
-use crate::{backend::core::AgentBackend, sdk::agent::Agent};
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+use crate::{
+    backend::core::{AgentBackend, ResponseStream},
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMOverrides},
+    sdk::{
+        agent::Agent, quota::Quota, recall::Recall, template::PromptTemplate, usage::TokenUsage,
+    },
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -32,9 +39,22 @@ where
     pub constitution: String,
 
     pub short_term_memory: String,
+    pub summarized_until: Option<HistoryCursor>,
 
     pub long_term_memory_size: usize,
 
+    // Applied on top of the agent's LLM configuration for this interaction.
+    pub llm_overrides: LLMOverrides,
+
+    // Enforced for this interaction on top of the agent's quota.
+    pub quota: Quota,
+
+    // Replaces the agent's prompt template for this interaction when set.
+    pub prompt_template: Option<PromptTemplate>,
+
+    // The participant the interaction is held with, when known.
+    pub participant_id: Option<Uuid>,
+
     pub state: PhantomData<State>,
 
     pub agent: Option<Box<Agent<Backend>>>,
@@ -46,14 +66,68 @@ pub struct Meta {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 
+    pub agent_id: Uuid,
     pub default_interaction_id: Uuid,
 }
 
+#[derive(Clone, Debug, Default)]
+pub enum InteractionsOrder {
+    CreatedAtAsc,
+    CreatedAtDesc,
+    UpdatedAtAsc,
+    #[default]
+    UpdatedAtDesc,
+}
+
+impl InteractionsOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InteractionsOrder::CreatedAtAsc => "created_at_asc",
+            InteractionsOrder::CreatedAtDesc => "created_at_desc",
+            InteractionsOrder::UpdatedAtAsc => "updated_at_asc",
+            InteractionsOrder::UpdatedAtDesc => "updated_at_desc",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InteractionsQuery {
+    pub limit: usize,
+    pub offset: usize,
+    pub order: InteractionsOrder,
+}
+
+impl Default for InteractionsQuery {
+    fn default() -> Self {
+        Self {
+            limit: 50,
+            offset: 0,
+            order: InteractionsOrder::default(),
+        }
+    }
+}
+
+// Points at a block in an interaction's history; a page fetched with this
+// cursor contains only blocks strictly older than it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistoryCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryPage {
+    pub blocks: Vec<InteractionBlock>,
+    pub next_cursor: Option<HistoryCursor>,
+}
+
 #[derive(Clone, Debug)]
 pub enum InteractionBlockRole {
     System,
     User,
     Agent,
+    ToolCall,
+    Tool,
 }
 
 impl InteractionBlockRole {
@@ -62,29 +136,34 @@ impl InteractionBlockRole {
             InteractionBlockRole::System => "system",
             InteractionBlockRole::User => "user",
             InteractionBlockRole::Agent => "agent",
+            InteractionBlockRole::ToolCall => "tool_call",
+            InteractionBlockRole::Tool => "tool",
         }
     }
 }
 
-impl ToString for InteractionBlockRole {
-    fn to_string(&self) -> String {
-        self.as_str().to_string()
+impl fmt::Display for InteractionBlockRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
 impl FromStr for InteractionBlockRole {
-    type Err = &'static str;
+    type Err = MyselfError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "system" => Ok(InteractionBlockRole::System),
             "user" => Ok(InteractionBlockRole::User),
             "agent" => Ok(InteractionBlockRole::Agent),
-            _ => Err("Invalid interaction block role"),
+            "tool_call" => Ok(InteractionBlockRole::ToolCall),
+            "tool" => Ok(InteractionBlockRole::Tool),
+            _ => Err(MyselfError::InvalidRole(s.to_string())),
         }
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct InteractionBlock {
     pub id: Uuid,
     pub created_at: DateTime<Utc>,
@@ -95,6 +174,23 @@ pub struct InteractionBlock {
 
     pub name: Option<String>,
 
+    // Links a tool call to its result; both blocks carry the same id.
+    pub tool_call_id: Option<String>,
+
+    // The participant who wrote a user block.
+    pub participant_id: Option<Uuid>,
+
+    // The agent that wrote an agent, tool call or tool block. Its usage is
+    // counted against that agent.
+    pub agent_id: Option<Uuid>,
+
+    // Set on the first block produced by each model call.
+    pub usage: Option<TokenUsage>,
+
+    // Stored with the block for semantic recall. Blocks read back from a
+    // database do not carry it.
+    pub embedding: Option<Vec<f32>>,
+
     pub interaction_id: Uuid,
 }
 
@@ -112,10 +208,22 @@ impl InteractionBlock {
             role,
             content,
             name,
+            tool_call_id: None,
+            participant_id: None,
+            agent_id: None,
+            usage: None,
+            embedding: None,
             interaction_id,
         }
     }
 
+    pub fn cursor(&self) -> HistoryCursor {
+        HistoryCursor {
+            created_at: self.created_at,
+            id: self.id,
+        }
+    }
+
     pub fn new_user(interaction_id: Uuid, content: String, name: Option<String>) -> Self {
         Self::new(InteractionBlockRole::User, content, interaction_id, name)
     }
@@ -127,6 +235,40 @@ impl InteractionBlock {
     pub fn new_system(interaction_id: Uuid, content: String, name: Option<String>) -> Self {
         Self::new(InteractionBlockRole::System, content, interaction_id, name)
     }
+
+    pub fn new_tool_call(
+        interaction_id: Uuid,
+        tool_name: String,
+        arguments: String,
+        tool_call_id: String,
+    ) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id),
+            ..Self::new(
+                InteractionBlockRole::ToolCall,
+                arguments,
+                interaction_id,
+                Some(tool_name),
+            )
+        }
+    }
+
+    pub fn new_tool_result(
+        interaction_id: Uuid,
+        tool_name: String,
+        content: String,
+        tool_call_id: String,
+    ) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id),
+            ..Self::new(
+                InteractionBlockRole::Tool,
+                content,
+                interaction_id,
+                Some(tool_name),
+            )
+        }
+    }
 }
 
 impl<Backend, State> Interaction<Backend, State>
@@ -177,11 +319,35 @@ where
             user_name: self.user_name.clone(),
             constitution: self.constitution.clone(),
             short_term_memory: self.short_term_memory.clone(),
+            summarized_until: self.summarized_until,
             long_term_memory_size: self.long_term_memory_size,
+            llm_overrides: self.llm_overrides.clone(),
+            quota: self.quota.clone(),
+            prompt_template: self.prompt_template.clone(),
+            participant_id: self.participant_id,
             agent: Some(Box::new(agent)),
             state: PhantomData,
         }
     }
+
+    pub fn without_agent(&self) -> Interaction<Backend, WithoutAgent> {
+        Interaction::<Backend, WithoutAgent> {
+            id: self.id,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            user_name: self.user_name.clone(),
+            constitution: self.constitution.clone(),
+            short_term_memory: self.short_term_memory.clone(),
+            summarized_until: self.summarized_until,
+            long_term_memory_size: self.long_term_memory_size,
+            llm_overrides: self.llm_overrides.clone(),
+            quota: self.quota.clone(),
+            prompt_template: self.prompt_template.clone(),
+            participant_id: self.participant_id,
+            agent: None,
+            state: PhantomData,
+        }
+    }
 }
 
 impl<Backend, State> Default for Interaction<Backend, State>
@@ -199,7 +365,12 @@ where
 
             constitution: "".to_string(),
             short_term_memory: "".to_string(),
+            summarized_until: None,
             long_term_memory_size: 0,
+            llm_overrides: LLMOverrides::default(),
+            quota: Quota::default(),
+            prompt_template: None,
+            participant_id: None,
             agent: None,
             state: PhantomData,
         }
@@ -213,6 +384,7 @@ impl Default for Meta {
             created_at: Utc::now(),
             updated_at: Utc::now(),
 
+            agent_id: Uuid::nil(),
             default_interaction_id: Uuid::new_v4(),
         }
     }
@@ -222,14 +394,68 @@ impl<Backend> Interaction<Backend, WithAgent>
 where
     Backend: AgentBackend + Sized + Default + Clone,
 {
-    pub async fn long_term_memory(&self, memory_size: usize) -> Vec<InteractionBlock> {
-        self.agent
-            .clone()
-            .unwrap()
-            .memory_engine()
+    pub async fn long_term_memory(
+        &self,
+        memory_size: usize,
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
+        self.agent()?
+            .memory_engine()?
             .get_interaction_long_term_memory(self.id, memory_size)
             .await
     }
+
+    // What the prompt for `input` remembers, as chosen by the agent's memory
+    // strategy.
+    pub async fn recall(
+        &self,
+        input: &InteractionBlock,
+        configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        self.agent()?
+            .memory_strategy
+            .recall(self, input, configuration)
+            .await
+    }
+
+    pub async fn history(
+        &self,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MyselfError> {
+        self.agent()?
+            .memory_engine()?
+            .get_interaction_history(self.id, before, limit)
+            .await
+    }
+
+    pub fn agent(&self) -> Result<&Agent<Backend>, MyselfError> {
+        self.agent
+            .as_deref()
+            .ok_or_else(|| MyselfError::Configuration("interaction has no agent".to_string()))
+    }
+
+    // The configuration requests for this interaction are made with: the
+    // agent's, or the backend's `base` when the agent has none, with the
+    // interaction's overrides applied.
+    pub fn llm_configuration(
+        &self,
+        base: &LLMConfiguration,
+    ) -> Result<LLMConfiguration, MyselfError> {
+        Ok(self
+            .agent()?
+            .llm_configuration
+            .as_ref()
+            .unwrap_or(base)
+            .with_overrides(&self.llm_overrides))
+    }
+
+    // The interaction's own template, or the agent's.
+    pub fn prompt_template(&self) -> Result<&PromptTemplate, MyselfError> {
+        match &self.prompt_template {
+            Some(template) => Ok(template),
+            None => Ok(&self.agent()?.prompt_template),
+        }
+    }
 }
 
 impl<Backend> Interaction<Backend, WithoutAgent>
@@ -240,12 +466,24 @@ where
         &self,
         agent: &mut Agent<Backend>,
         memory_size: usize,
-    ) -> Vec<InteractionBlock> {
+    ) -> Result<Vec<InteractionBlock>, MyselfError> {
         agent
-            .memory_engine()
+            .memory_engine()?
             .get_interaction_long_term_memory(self.id, memory_size)
             .await
     }
+
+    pub async fn history(
+        &self,
+        agent: &mut Agent<Backend>,
+        before: Option<HistoryCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MyselfError> {
+        agent
+            .memory_engine()?
+            .get_interaction_history(self.id, before, limit)
+            .await
+    }
 }
 
 impl<Backend> Interaction<Backend, WithAgent>
@@ -255,7 +493,28 @@ where
     pub async fn interact(
         &mut self,
         message: &String,
-    ) -> Option<(InteractionBlock, InteractionBlock)> {
-        self.agent.clone().unwrap().interact(self.id, message).await
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.agent()?.clone().interact(self.id, message).await
+    }
+
+    pub async fn interact_as(
+        &mut self,
+        participant_id: Uuid,
+        message: &String,
+    ) -> Result<(InteractionBlock, InteractionBlock), MyselfError> {
+        self.agent()?
+            .clone()
+            .interact_as(self.id, participant_id, message)
+            .await
+    }
+
+    pub async fn interact_stream(&mut self, message: &String) -> Result<ResponseStream, MyselfError>
+    where
+        Backend: 'static,
+    {
+        self.agent()?
+            .clone()
+            .interact_stream(self.id, message)
+            .await
     }
 }
@@ -1,3 +1,19 @@
 pub mod agent;
 
+pub mod group;
+
 pub mod interaction;
+
+pub mod orchestration;
+
+pub mod participant;
+
+pub mod quota;
+
+pub mod recall;
+
+pub mod template;
+
+pub mod tool;
+
+pub mod usage;
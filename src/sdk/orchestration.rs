@@ -0,0 +1,232 @@
+use std::{fmt, sync::Arc};
+
+use uuid::Uuid;
+
+use crate::{
+    backend::core::AgentBackend,
+    error::MyselfError,
+    sdk::{
+        agent::Agent,
+        group::{GroupChat, RoundRobin, SpeakerContext, SpeakerSelector, SPEAKER_HISTORY_SIZE},
+        interaction::InteractionBlock,
+    },
+};
+
+const DEFAULT_MAX_TURNS: usize = 10;
+
+pub type StopCondition = Arc<dyn Fn(&InteractionBlock) -> bool + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // The stop condition held for the latest response.
+    Condition,
+    MaxTurns,
+}
+
+// The responses given while an orchestration ran, in order.
+#[derive(Clone, Debug)]
+pub struct Conversation {
+    pub interaction_id: Uuid,
+    pub turns: Vec<InteractionBlock>,
+    pub stop_reason: StopReason,
+}
+
+// Agents talking to each other in a shared group interaction. Each turn, the
+// speaker selector picks the agent that answers the latest message, until a
+// response meets the stop condition or `max_turns` responses have been given.
+// Every message is stored in the interaction as it is written.
+#[derive(Clone)]
+pub struct Orchestration<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    pub max_turns: usize,
+
+    agents: Vec<Agent<Backend>>,
+    selector: Arc<dyn SpeakerSelector>,
+    stop_condition: Option<StopCondition>,
+}
+
+impl<Backend> fmt::Debug for Orchestration<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Orchestration")
+            .field("max_turns", &self.max_turns)
+            .field(
+                "agents",
+                &self
+                    .agents
+                    .iter()
+                    .map(|agent| &agent.my_name)
+                    .collect::<Vec<_>>(),
+            )
+            .field("selector", &self.selector)
+            .field("stop_condition", &self.stop_condition.is_some())
+            .finish()
+    }
+}
+
+impl<Backend> Orchestration<Backend>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    // Agents take turns in the order given unless another selector is set.
+    pub fn new(agents: Vec<Agent<Backend>>) -> Self {
+        Self {
+            max_turns: DEFAULT_MAX_TURNS,
+            agents,
+            selector: Arc::new(RoundRobin),
+            stop_condition: None,
+        }
+    }
+
+    pub fn selector(mut self, selector: impl SpeakerSelector + 'static) -> Self {
+        self.selector = Arc::new(selector);
+        self
+    }
+
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns;
+        self
+    }
+
+    pub fn stop_when(
+        mut self,
+        condition: impl Fn(&InteractionBlock) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.stop_condition = Some(Arc::new(condition));
+        self
+    }
+
+    // Stops once a response contains `phrase`.
+    pub fn stop_on(self, phrase: impl Into<String>) -> Self {
+        let phrase = phrase.into();
+
+        self.stop_when(move |block| block.content.contains(&phrase))
+    }
+
+    pub fn agents(&self) -> &[Agent<Backend>] {
+        &self.agents
+    }
+
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        if self.agents.is_empty() {
+            return Err(MyselfError::Configuration(
+                "orchestration needs at least one agent".to_string(),
+            ));
+        }
+
+        if self.max_turns == 0 {
+            return Err(MyselfError::Configuration(
+                "orchestration max_turns must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Starts a new group interaction between the agents, in which the first
+    // agent chosen answers `opening`.
+    pub async fn start(
+        &mut self,
+        constitution: String,
+        opening: &String,
+    ) -> Result<Conversation, MyselfError> {
+        self.validate()?;
+
+        let chat = GroupChat::start(self.agents.clone(), &[], constitution).await?;
+
+        self.run(chat.interaction_id, Some(opening)).await
+    }
+
+    // Continues the group interaction `interaction_id`, of which all the agents
+    // must be members, from its latest message.
+    pub async fn resume(&mut self, interaction_id: Uuid) -> Result<Conversation, MyselfError> {
+        self.validate()?;
+
+        GroupChat::open(interaction_id, self.agents.clone()).await?;
+
+        self.run(interaction_id, None).await
+    }
+
+    async fn run(
+        &mut self,
+        interaction_id: Uuid,
+        mut opening: Option<&String>,
+    ) -> Result<Conversation, MyselfError> {
+        let memory_engine = self.agents[0].memory_engine()?;
+
+        let agent_names = self
+            .agents
+            .iter()
+            .map(|agent| agent.my_name.clone())
+            .collect::<Vec<_>>();
+
+        let mut turns = Vec::new();
+
+        while turns.len() < self.max_turns {
+            let history = memory_engine
+                .get_interaction_long_term_memory(interaction_id, SPEAKER_HISTORY_SIZE)
+                .await?;
+
+            let input = match opening {
+                Some(message) => InteractionBlock::new_user(
+                    interaction_id,
+                    message.to_owned(),
+                    Some(self.agents[0].default_interaction.user_name.clone()),
+                ),
+                None => history.last().cloned().ok_or_else(|| {
+                    MyselfError::Configuration(format!(
+                        "interaction {} has nothing to respond to",
+                        interaction_id
+                    ))
+                })?,
+            };
+
+            let index = self
+                .selector
+                .select(&SpeakerContext {
+                    agent_names: &agent_names,
+                    input: &input,
+                    history: &history,
+                })
+                .await?;
+
+            let agent = self.agents.get_mut(index).ok_or_else(|| {
+                MyselfError::Configuration(format!(
+                    "speaker selector chose agent {} of {}",
+                    index,
+                    agent_names.len()
+                ))
+            })?;
+
+            let response = match opening.take() {
+                Some(message) => agent.interact(interaction_id, message).await?.1,
+                None => agent.respond(interaction_id).await?,
+            };
+
+            let stop = self
+                .stop_condition
+                .as_ref()
+                .is_some_and(|condition| condition(&response));
+
+            turns.push(response);
+
+            if stop {
+                return Ok(Conversation {
+                    interaction_id,
+                    turns,
+                    stop_reason: StopReason::Condition,
+                });
+            }
+        }
+
+        Ok(Conversation {
+            interaction_id,
+            turns,
+            stop_reason: StopReason::MaxTurns,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::error::MyselfError;
+
+// Someone who takes part in interactions. User blocks record the participant
+// who wrote them, so a participant's messages can be told apart from others'
+// and their interactions can be looked up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Participant {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+
+    pub display_name: String,
+    // An identifier in another system, such as a chat platform's user id.
+    // Unique among participants.
+    pub external_id: Option<String>,
+    pub metadata: Map<String, Value>,
+}
+
+impl Participant {
+    pub fn new(display_name: impl Into<String>) -> Self {
+        let now = Utc::now();
+
+        Self {
+            id: Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            display_name: display_name.into(),
+            external_id: None,
+            metadata: Map::new(),
+        }
+    }
+
+    pub fn external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        if self.display_name.trim().is_empty() {
+            return Err(MyselfError::Configuration(
+                "participant display_name must not be empty".to_string(),
+            ));
+        }
+
+        if self
+            .external_id
+            .as_deref()
+            .is_some_and(|external_id| external_id.is_empty())
+        {
+            return Err(MyselfError::Configuration(
+                "participant external_id must not be empty".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::MyselfError;
+
+// Limits on the model calls made for an agent or a single interaction. Token
+// and request limits apply to a rolling window ending now; the spend limit
+// covers all recorded usage. Unset limits are not enforced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quota {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens_per_day: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+}
+
+impl Quota {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn tokens_per_day(mut self, tokens: usize) -> Self {
+        self.tokens_per_day = Some(tokens);
+        self
+    }
+
+    pub fn requests_per_minute(mut self, requests: usize) -> Self {
+        self.requests_per_minute = Some(requests);
+        self
+    }
+
+    pub fn max_cost(mut self, cost: f64) -> Self {
+        self.max_cost = Some(cost);
+        self
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        if let Some(cost) = self.max_cost {
+            if !cost.is_finite() || cost < 0.0 {
+                return Err(MyselfError::Configuration(format!(
+                    "max_cost must be a non-negative number, got {}",
+                    cost
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaScope {
+    Agent,
+    Interaction,
+}
+
+impl fmt::Display for QuotaScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaScope::Agent => f.write_str("agent"),
+            QuotaScope::Interaction => f.write_str("interaction"),
+        }
+    }
+}
+
+// The limit that was reached, with its configured value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuotaLimit {
+    TokensPerDay(usize),
+    RequestsPerMinute(usize),
+    MaxCost(f64),
+}
+
+impl fmt::Display for QuotaLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaLimit::TokensPerDay(tokens) => write!(f, "{} tokens per day", tokens),
+            QuotaLimit::RequestsPerMinute(requests) => {
+                write!(f, "{} requests per minute", requests)
+            }
+            QuotaLimit::MaxCost(cost) => write!(f, "total cost of {}", cost),
+        }
+    }
+}
+
+// What is left of a quota; `None` means the limit is not set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Allowance {
+    pub scope: QuotaScope,
+    pub tokens_today: Option<usize>,
+    pub requests_this_minute: Option<usize>,
+    pub cost: Option<f64>,
+}
+
+impl Allowance {
+    pub fn unlimited(scope: QuotaScope) -> Self {
+        Self {
+            scope,
+            tokens_today: None,
+            requests_this_minute: None,
+            cost: None,
+        }
+    }
+
+    // Fails with the first limit of `quota` that has nothing left.
+    pub fn check(&self, quota: &Quota) -> Result<(), MyselfError> {
+        let limit = if self.tokens_today == Some(0) {
+            quota.tokens_per_day.map(QuotaLimit::TokensPerDay)
+        } else if self.requests_this_minute == Some(0) {
+            quota.requests_per_minute.map(QuotaLimit::RequestsPerMinute)
+        } else if self.cost.is_some_and(|cost| cost <= 0.0) {
+            quota.max_cost.map(QuotaLimit::MaxCost)
+        } else {
+            None
+        };
+
+        match limit {
+            Some(limit) => Err(MyselfError::QuotaExceeded {
+                scope: self.scope,
+                limit,
+            }),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use crate::{
+    backend::{context::TokenCounter, core::AgentBackend, openai::chat_message},
+    error::MyselfError,
+    llm::LLMConfiguration,
+    sdk::interaction::{HistoryCursor, Interaction, InteractionBlock, WithAgent},
+};
+
+const TOKEN_WINDOW_PAGE_SIZE: usize = 50;
+
+// What the prompt for an input remembers of its interaction.
+#[derive(Clone, Debug, Default)]
+pub struct Recall {
+    // The interaction's short-term memory, when it is to be included.
+    pub summary: Option<String>,
+    // Sent as the conversation so far, in chronological order.
+    pub history: Vec<InteractionBlock>,
+    // Listed as relevant earlier messages, in chronological order.
+    pub relevant: Vec<InteractionBlock>,
+}
+
+#[async_trait]
+pub trait MemoryStrategy<Backend>
+where
+    Self: Debug + Send + Sync,
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    // Chooses what the prompt for `input` remembers. `configuration` is the one
+    // the prompt is sent with.
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        input: &InteractionBlock,
+        configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError>;
+
+    // Whether stored blocks and inputs need to be embedded.
+    fn uses_embeddings(&self) -> bool {
+        false
+    }
+
+    fn validate(&self) -> Result<(), MyselfError> {
+        Ok(())
+    }
+}
+
+fn summary<Backend>(interaction: &Interaction<Backend, WithAgent>) -> Option<String>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    let summary = interaction.short_term_memory.trim();
+
+    (!summary.is_empty()).then(|| summary.to_string())
+}
+
+// The interaction's `long_term_memory_size` most recent blocks, leaving out
+// those already condensed into its short-term memory.
+async fn unsummarized_history<Backend>(
+    interaction: &Interaction<Backend, WithAgent>,
+) -> Result<Vec<InteractionBlock>, MyselfError>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    let history = interaction
+        .long_term_memory(interaction.long_term_memory_size)
+        .await?;
+
+    let Some(until) = interaction.summarized_until else {
+        return Ok(history);
+    };
+
+    Ok(history
+        .into_iter()
+        .filter(|block| (block.created_at, block.id) > (until.created_at, until.id))
+        .collect())
+}
+
+fn validate_semantic(top_k: usize, min_similarity: f32) -> Result<(), MyselfError> {
+    if top_k == 0 {
+        return Err(MyselfError::Configuration(
+            "semantic recall top_k must be greater than 0".to_string(),
+        ));
+    }
+
+    if !(-1.0..=1.0).contains(&min_similarity) {
+        return Err(MyselfError::Configuration(format!(
+            "semantic recall min_similarity must be between -1 and 1, got {}",
+            min_similarity
+        )));
+    }
+
+    Ok(())
+}
+
+// The `top_k` blocks older than `before` most similar to `input`, in
+// chronological order. The input itself is left out when it is already
+// stored, as when an agent responds to another agent's message. Inputs
+// without an embedding recall nothing.
+async fn relevant_blocks<Backend>(
+    interaction: &Interaction<Backend, WithAgent>,
+    input: &InteractionBlock,
+    before: Option<HistoryCursor>,
+    top_k: usize,
+    min_similarity: f32,
+) -> Result<Vec<InteractionBlock>, MyselfError>
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    let Some(embedding) = &input.embedding else {
+        return Ok(Vec::new());
+    };
+
+    let mut blocks = interaction
+        .agent()?
+        .memory_engine()?
+        .search_interaction_blocks(interaction.id, embedding, before, top_k + 1)
+        .await?
+        .into_iter()
+        .filter(|(block, similarity)| block.id != input.id && *similarity >= min_similarity)
+        .map(|(block, _)| block)
+        .take(top_k)
+        .collect::<Vec<_>>();
+
+    blocks.sort_by_key(|block| (block.created_at, block.id));
+
+    Ok(blocks)
+}
+
+// The interaction's `long_term_memory_size` most recent blocks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SlidingWindow;
+
+#[async_trait]
+impl<Backend> MemoryStrategy<Backend> for SlidingWindow
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        _input: &InteractionBlock,
+        _configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        Ok(Recall {
+            history: interaction
+                .long_term_memory(interaction.long_term_memory_size)
+                .await?,
+            ..Default::default()
+        })
+    }
+}
+
+// As many of the most recent blocks as fit in `max_tokens`, counted with the
+// tokenizer of the model the prompt is sent to.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenWindow {
+    pub max_tokens: usize,
+}
+
+impl TokenWindow {
+    pub fn new(max_tokens: usize) -> Self {
+        Self { max_tokens }
+    }
+}
+
+#[async_trait]
+impl<Backend> MemoryStrategy<Backend> for TokenWindow
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        _input: &InteractionBlock,
+        configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        let counter = TokenCounter::for_model(&configuration.model_name)?;
+
+        let mut history = Vec::new();
+        let mut tokens = 0;
+        let mut before = None;
+
+        'pages: loop {
+            let page = interaction.history(before, TOKEN_WINDOW_PAGE_SIZE).await?;
+
+            for block in page.blocks.into_iter().rev() {
+                tokens += counter.count_message(&chat_message(&block));
+
+                if tokens > self.max_tokens {
+                    break 'pages;
+                }
+
+                history.push(block);
+            }
+
+            match page.next_cursor {
+                Some(cursor) => before = Some(cursor),
+                None => break,
+            }
+        }
+
+        history.reverse();
+
+        Ok(Recall {
+            history,
+            ..Default::default()
+        })
+    }
+
+    fn validate(&self) -> Result<(), MyselfError> {
+        if self.max_tokens == 0 {
+            return Err(MyselfError::Configuration(
+                "token window max_tokens must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// The interaction's short-term memory and its `long_term_memory_size` most
+// recent blocks that it does not cover. Agents use this unless configured
+// otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SummaryWindow;
+
+#[async_trait]
+impl<Backend> MemoryStrategy<Backend> for SummaryWindow
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        _input: &InteractionBlock,
+        _configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        Ok(Recall {
+            summary: summary(interaction),
+            history: unsummarized_history(interaction).await?,
+            ..Default::default()
+        })
+    }
+}
+
+// Only the `top_k` blocks most similar to the input, leaving out those less
+// similar than `min_similarity`. Needs a backend with embeddings.
+#[derive(Clone, Copy, Debug)]
+pub struct SemanticTopK {
+    pub top_k: usize,
+    pub min_similarity: f32,
+}
+
+impl SemanticTopK {
+    pub fn new(top_k: usize, min_similarity: f32) -> Self {
+        Self {
+            top_k,
+            min_similarity,
+        }
+    }
+}
+
+#[async_trait]
+impl<Backend> MemoryStrategy<Backend> for SemanticTopK
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        input: &InteractionBlock,
+        _configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        Ok(Recall {
+            relevant: relevant_blocks(interaction, input, None, self.top_k, self.min_similarity)
+                .await?,
+            ..Default::default()
+        })
+    }
+
+    fn uses_embeddings(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), MyselfError> {
+        validate_semantic(self.top_k, self.min_similarity)
+    }
+}
+
+// `SummaryWindow`, plus the `top_k` blocks older than the window that are most
+// similar to the input. Needs a backend with embeddings.
+#[derive(Clone, Copy, Debug)]
+pub struct Hybrid {
+    pub top_k: usize,
+    pub min_similarity: f32,
+}
+
+impl Hybrid {
+    pub fn new(top_k: usize, min_similarity: f32) -> Self {
+        Self {
+            top_k,
+            min_similarity,
+        }
+    }
+}
+
+#[async_trait]
+impl<Backend> MemoryStrategy<Backend> for Hybrid
+where
+    Backend: AgentBackend + Sized + Default + Clone,
+{
+    async fn recall(
+        &self,
+        interaction: &Interaction<Backend, WithAgent>,
+        input: &InteractionBlock,
+        _configuration: &LLMConfiguration,
+    ) -> Result<Recall, MyselfError> {
+        let history = unsummarized_history(interaction).await?;
+
+        let relevant = relevant_blocks(
+            interaction,
+            input,
+            history.first().map(InteractionBlock::cursor),
+            self.top_k,
+            self.min_similarity,
+        )
+        .await?;
+
+        Ok(Recall {
+            summary: summary(interaction),
+            history,
+            relevant,
+        })
+    }
+
+    fn uses_embeddings(&self) -> bool {
+        true
+    }
+
+    fn validate(&self) -> Result<(), MyselfError> {
+        validate_semantic(self.top_k, self.min_similarity)
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::MyselfError, sdk::interaction::InteractionBlock};
+
+// Variables every part of a prompt template can use.
+pub const PROMPT_VARIABLES: &[&str] = &[
+    "agent_name",
+    "user_name",
+    "constitution",
+    "summary",
+    "history",
+    "relevant",
+    "input",
+    "date",
+];
+
+// Variables the `message` part can use, describing the message being rendered.
+pub const MESSAGE_VARIABLES: &[&str] = &["speaker", "name", "role", "content"];
+
+// How the system messages of a prompt and the transcripts inside them are
+// laid out. Placeholders are written `{{variable}}`; besides the built-in
+// variables, templates can use their own `variables`.
+//
+// Each of `system`, `summary` and `relevant` renders to a system message, left
+// out when it renders empty. `summary` and `relevant` are only rendered when
+// there is a summary or relevant messages to show. A template that uses
+// `{{history}}` gets the history as a transcript instead of as messages.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplate {
+    pub system: String,
+    pub summary: String,
+    pub relevant: String,
+    // Renders each message of the `history` and `relevant` transcripts.
+    pub message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            system: "{{constitution}}".to_string(),
+            summary: "Summary of the conversation so far:\n{{summary}}".to_string(),
+            relevant: "Earlier messages that may be relevant:\n{{relevant}}".to_string(),
+            message: "{{speaker}}: {{content}}".to_string(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+// The values of the built-in variables for a single prompt.
+#[derive(Clone, Debug, Default)]
+pub struct PromptContext<'a> {
+    pub agent_name: &'a str,
+    pub user_name: &'a str,
+    pub constitution: &'a str,
+    pub summary: Option<&'a str>,
+    pub history: &'a [InteractionBlock],
+    pub relevant: &'a [InteractionBlock],
+    pub input: &'a str,
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Segment<'_>>, MyselfError> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+
+        let end = rest[start..].find("}}").ok_or_else(|| {
+            MyselfError::Configuration(format!("unclosed placeholder in template {:?}", template))
+        })?;
+
+        let name = rest[start + 2..start + end].trim();
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(MyselfError::Configuration(format!(
+                "invalid placeholder {{{{{}}}}} in template {:?}",
+                name, template
+            )));
+        }
+
+        segments.push(Segment::Variable(name));
+        rest = &rest[start + end + 2..];
+    }
+
+    segments.push(Segment::Text(rest));
+
+    Ok(segments)
+}
+
+fn render(template: &str, values: &HashMap<&str, String>) -> Result<String, MyselfError> {
+    parse(template)?
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => Ok(text),
+            Segment::Variable(name) => values.get(name).map(String::as_str).ok_or_else(|| {
+                MyselfError::Configuration(format!("missing template variable {:?}", name))
+            }),
+        })
+        .collect()
+}
+
+impl PromptTemplate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn system(mut self, template: impl Into<String>) -> Self {
+        self.system = template.into();
+        self
+    }
+
+    pub fn summary(mut self, template: impl Into<String>) -> Self {
+        self.summary = template.into();
+        self
+    }
+
+    pub fn relevant(mut self, template: impl Into<String>) -> Self {
+        self.relevant = template.into();
+        self
+    }
+
+    pub fn message(mut self, template: impl Into<String>) -> Self {
+        self.message = template.into();
+        self
+    }
+
+    pub fn variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    // Fails on malformed placeholders and on variables that are neither
+    // built-in nor defined by the template.
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        for (part, template, builtins) in [
+            ("system", &self.system, PROMPT_VARIABLES),
+            ("summary", &self.summary, PROMPT_VARIABLES),
+            ("relevant", &self.relevant, PROMPT_VARIABLES),
+            ("message", &self.message, MESSAGE_VARIABLES),
+        ] {
+            for segment in parse(template)? {
+                if let Segment::Variable(name) = segment {
+                    if !builtins.contains(&name) && !self.variables.contains_key(name) {
+                        return Err(MyselfError::Configuration(format!(
+                            "{} template uses undefined variable {:?}",
+                            part, name
+                        )));
+                    }
+                }
+            }
+        }
+
+        for name in self.variables.keys() {
+            if PROMPT_VARIABLES.contains(&name.as_str())
+                || MESSAGE_VARIABLES.contains(&name.as_str())
+            {
+                return Err(MyselfError::Configuration(format!(
+                    "template variable {:?} shadows a built-in variable",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Whether any prompt part uses `variable`.
+    pub fn uses(&self, variable: &str) -> bool {
+        [&self.system, &self.summary, &self.relevant]
+            .into_iter()
+            .any(|template| {
+                parse(template).is_ok_and(|segments| {
+                    segments.iter().any(
+                        |segment| matches!(segment, Segment::Variable(name) if *name == variable),
+                    )
+                })
+            })
+    }
+
+    pub fn transcript(&self, blocks: &[InteractionBlock]) -> Result<String, MyselfError> {
+        blocks
+            .iter()
+            .map(|block| {
+                let role = block.role.to_string();
+                let speaker = match &block.name {
+                    Some(name) => format!("{} ({})", name, role),
+                    None => role.clone(),
+                };
+
+                let mut values = self.custom_values();
+                values.insert("speaker", speaker);
+                values.insert("name", block.name.clone().unwrap_or_default());
+                values.insert("role", role);
+                values.insert("content", block.content.clone());
+
+                render(&self.message, &values)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|lines| lines.join("\n"))
+    }
+
+    // The system messages of a prompt, in order.
+    pub fn render(&self, context: &PromptContext) -> Result<Vec<String>, MyselfError> {
+        let mut values = self.custom_values();
+        values.insert("agent_name", context.agent_name.to_string());
+        values.insert("user_name", context.user_name.to_string());
+        values.insert("constitution", context.constitution.trim().to_string());
+        values.insert("summary", context.summary.unwrap_or_default().to_string());
+        values.insert("history", self.transcript(context.history)?);
+        values.insert("relevant", self.transcript(context.relevant)?);
+        values.insert("input", context.input.to_string());
+        values.insert("date", Utc::now().format("%Y-%m-%d").to_string());
+
+        let mut parts = vec![&self.system];
+
+        if context.summary.is_some() {
+            parts.push(&self.summary);
+        }
+
+        if !context.relevant.is_empty() {
+            parts.push(&self.relevant);
+        }
+
+        Ok(parts
+            .into_iter()
+            .map(|template| render(template, &values))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|message| message.trim().to_string())
+            .filter(|message| !message.is_empty())
+            .collect())
+    }
+
+    fn custom_values(&self) -> HashMap<&str, String> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn error<T>(result: Result<T, MyselfError>) -> String {
+        match result {
+            Err(MyselfError::Configuration(message)) => message,
+            Err(other) => panic!("expected a configuration error, got {:?}", other),
+            Ok(_) => panic!("expected a configuration error"),
+        }
+    }
+
+    fn variables(template: &str) -> Vec<&str> {
+        parse(template)
+            .unwrap()
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Variable(name) => Some(name),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    fn context<'a>(
+        summary: Option<&'a str>,
+        history: &'a [InteractionBlock],
+        relevant: &'a [InteractionBlock],
+    ) -> PromptContext<'a> {
+        PromptContext {
+            agent_name: "Bot",
+            user_name: "Ana",
+            constitution: "  Be brief.  ",
+            summary,
+            history,
+            relevant,
+            input: "hi",
+        }
+    }
+
+    #[test]
+    fn placeholders_are_parsed_and_trimmed() {
+        assert_eq!(
+            variables("{{agent_name}} and {{ user_name }}"),
+            ["agent_name", "user_name"]
+        );
+        assert_eq!(variables("no placeholders"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert!(error(parse("Hello {{user_name")).starts_with("unclosed placeholder"));
+        assert!(error(parse("Hello {{user_name} }")).starts_with("unclosed placeholder"));
+    }
+
+    #[test]
+    fn invalid_placeholders_are_rejected() {
+        for template in ["{{}}", "{{ }}", "{{user name}}", "{{user-name}}", "{{a.b}}"] {
+            assert!(
+                error(parse(template)).starts_with("invalid placeholder"),
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn the_default_template_is_valid() {
+        PromptTemplate::default().validate().unwrap();
+    }
+
+    #[test]
+    fn undefined_variables_are_rejected() {
+        assert_eq!(
+            error(PromptTemplate::new().system("{{mood}}").validate()),
+            "system template uses undefined variable \"mood\""
+        );
+
+        PromptTemplate::new()
+            .system("{{mood}}")
+            .variable("mood", "cheerful")
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn each_part_only_sees_its_own_built_in_variables() {
+        assert_eq!(
+            error(PromptTemplate::new().message("{{input}}").validate()),
+            "message template uses undefined variable \"input\""
+        );
+        assert_eq!(
+            error(PromptTemplate::new().summary("{{content}}").validate()),
+            "summary template uses undefined variable \"content\""
+        );
+    }
+
+    #[test]
+    fn variables_cannot_shadow_built_in_names() {
+        for name in ["user_name", "content"] {
+            assert_eq!(
+                error(PromptTemplate::new().variable(name, "x").validate()),
+                format!("template variable {:?} shadows a built-in variable", name)
+            );
+        }
+    }
+
+    #[test]
+    fn render_fills_in_the_variables() {
+        let template = PromptTemplate::new()
+            .system("{{agent_name}} talks to {{user_name}} in a {{mood}} way. {{constitution}}")
+            .variable("mood", "cheerful");
+
+        assert_eq!(
+            template.render(&context(None, &[], &[])).unwrap(),
+            ["Bot talks to Ana in a cheerful way. Be brief."]
+        );
+    }
+
+    #[test]
+    fn render_fails_on_undefined_variables() {
+        let template = PromptTemplate::new().system("{{mood}}");
+
+        assert_eq!(
+            error(template.render(&context(None, &[], &[]))),
+            "missing template variable \"mood\""
+        );
+    }
+
+    #[test]
+    fn render_leaves_out_empty_parts() {
+        let interaction_id = Uuid::new_v4();
+        let relevant = [InteractionBlock::new_user(
+            interaction_id,
+            "cats purr".to_string(),
+            Some("Ana".to_string()),
+        )];
+
+        assert_eq!(
+            PromptTemplate::default()
+                .render(&context(None, &[], &[]))
+                .unwrap(),
+            ["Be brief."]
+        );
+
+        assert_eq!(
+            PromptTemplate::default()
+                .render(&context(Some("They met."), &[], &relevant))
+                .unwrap(),
+            [
+                "Be brief.",
+                "Summary of the conversation so far:\nThey met.",
+                "Earlier messages that may be relevant:\nAna (user): cats purr",
+            ]
+        );
+
+        assert_eq!(
+            PromptTemplate::new()
+                .system("  ")
+                .summary("")
+                .render(&context(Some("They met."), &[], &[]))
+                .unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn transcripts_render_each_message() {
+        let interaction_id = Uuid::new_v4();
+        let history = [
+            InteractionBlock::new_user(interaction_id, "hi".to_string(), Some("Ana".to_string())),
+            InteractionBlock::new_agent(interaction_id, "hello".to_string(), None),
+        ];
+
+        assert_eq!(
+            PromptTemplate::default().transcript(&history).unwrap(),
+            "Ana (user): hi\nagent: hello"
+        );
+
+        let template = PromptTemplate::new()
+            .system("{{history}}")
+            .message("[{{role}}] {{name}}: {{content}}");
+
+        assert!(template.uses("history"));
+        assert!(!template.uses("input"));
+        assert_eq!(
+            template.render(&context(None, &history, &[])).unwrap(),
+            ["[user] Ana: hi\n[agent] : hello"]
+        );
+    }
+}
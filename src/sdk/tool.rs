@@ -0,0 +1,107 @@
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::error::MyselfError;
+
+#[async_trait]
+pub trait Tool
+where
+    Self: Send + Sync,
+{
+    fn name(&self) -> String;
+
+    fn description(&self) -> String;
+
+    // JSON schema of the object `call` receives as its arguments.
+    fn parameters(&self) -> Value;
+
+    async fn call(&self, arguments: Value) -> Result<String, MyselfError>;
+}
+
+#[derive(Clone, Default)]
+pub struct Toolbox {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl Toolbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, tool: impl Tool + 'static) -> &mut Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.name() == name).cloned()
+    }
+
+    pub fn tools(&self) -> &[Arc<dyn Tool>] {
+        &self.tools
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    // Tool names are sent to the model as function names, which must match
+    // ^[a-zA-Z0-9_-]{1,64}$ and be unique.
+    pub fn validate(&self) -> Result<(), MyselfError> {
+        let mut names = HashSet::new();
+
+        for tool in &self.tools {
+            let name = tool.name();
+
+            let valid = !name.is_empty()
+                && name.len() <= 64
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+            if !valid {
+                return Err(MyselfError::Configuration(format!(
+                    "invalid tool name: {}",
+                    name
+                )));
+            }
+
+            if !names.insert(name.clone()) {
+                return Err(MyselfError::Configuration(format!(
+                    "duplicated tool name: {}",
+                    name
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Runs the named tool. Failures are returned as the call's result so the
+    // model can see what went wrong and recover.
+    pub async fn call(&self, name: &str, arguments: &str) -> String {
+        let Some(tool) = self.get(name) else {
+            return format!("error: unknown tool {}", name);
+        };
+
+        let arguments = match serde_json::from_str::<Value>(arguments) {
+            Ok(arguments) => arguments,
+            Err(err) => return format!("error: invalid arguments: {}", err),
+        };
+
+        match tool.call(arguments).await {
+            Ok(result) => result,
+            Err(err) => format!("error: {}", err),
+        }
+    }
+}
+
+impl fmt::Debug for Toolbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.tools.iter().map(|tool| tool.name()))
+            .finish()
+    }
+}
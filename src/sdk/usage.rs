@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+// Tokens spent by the model call that produced a block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub model_name: String,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub finish_reason: Option<String>,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+// Usage aggregated over the responses of a single model.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModelUsage {
+    pub model_name: String,
+    pub responses: usize,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+}
+
+impl ModelUsage {
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn cost(&self, prices: &PriceTable) -> Option<f64> {
+        let price = prices.price(&self.model_name)?;
+
+        Some(
+            (self.prompt_tokens as f64 * price.prompt
+                + self.completion_tokens as f64 * price.completion)
+                / 1_000_000.0,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsageReport {
+    pub models: Vec<ModelUsage>,
+}
+
+impl UsageReport {
+    pub fn responses(&self) -> usize {
+        self.models.iter().map(|usage| usage.responses).sum()
+    }
+
+    pub fn prompt_tokens(&self) -> usize {
+        self.models.iter().map(|usage| usage.prompt_tokens).sum()
+    }
+
+    pub fn completion_tokens(&self) -> usize {
+        self.models
+            .iter()
+            .map(|usage| usage.completion_tokens)
+            .sum()
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        self.prompt_tokens() + self.completion_tokens()
+    }
+
+    // The total cost, or `None` if any of the models used has no price.
+    pub fn cost(&self, prices: &PriceTable) -> Option<f64> {
+        self.models.iter().map(|usage| usage.cost(prices)).sum()
+    }
+}
+
+// Prices in any currency per million tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+// Providers report dated model names such as `gpt-4-0613`, so a model without
+// an exact price uses the price of its longest listed prefix.
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_price(
+        mut self,
+        model_name: impl Into<String>,
+        prompt_per_million: f64,
+        completion_per_million: f64,
+    ) -> Self {
+        self.prices.insert(
+            model_name.into(),
+            ModelPrice {
+                prompt: prompt_per_million,
+                completion: completion_per_million,
+            },
+        );
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    pub fn price(&self, model_name: &str) -> Option<&ModelPrice> {
+        self.prices.get(model_name).or_else(|| {
+            self.prices
+                .iter()
+                .filter(|(prefix, _)| model_name.starts_with(prefix.as_str()))
+                .max_by_key(|(prefix, _)| prefix.len())
+                .map(|(_, price)| price)
+        })
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::{from_slice, json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use myself::{
+    backend::local::LocalBackend,
+    database::memory::MemoryEngine,
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMDialect, LLMEngineError, RetryPolicy},
+    sdk::{
+        agent::{Agent, AgentBuilder},
+        interaction::InteractionBlockRole,
+        template::PromptTemplate,
+        tool::Tool,
+    },
+};
+
+struct Weather;
+
+#[async_trait]
+impl Tool for Weather {
+    fn name(&self) -> String {
+        "get_weather".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Current weather of a city".to_string()
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"],
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, MyselfError> {
+        Ok(format!(
+            "sunny in {}",
+            arguments["city"].as_str().unwrap_or("?")
+        ))
+    }
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(body: Value) -> Self {
+        Self::status(200, body)
+    }
+
+    fn status(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn events(events: &[Value]) -> Self {
+        let mut body = events
+            .iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect::<String>();
+        body.push_str("data: [DONE]\n\n");
+
+        Self {
+            status: 200,
+            content_type: "text/event-stream",
+            body,
+        }
+    }
+
+    fn lines(lines: &[Value]) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/x-ndjson",
+            body: lines.iter().map(|line| format!("{}\n", line)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Request {
+    path: String,
+    body: Value,
+}
+
+// A server that answers each request with the next of `replies`, one
+// connection per request, and records what it was sent.
+#[derive(Clone)]
+struct Server {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl Server {
+    async fn start(replies: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut replies = VecDeque::from(replies);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let request = read_request(&mut stream).await;
+                recorded.lock().unwrap().push(request);
+
+                let reply = replies
+                    .pop_front()
+                    .unwrap_or_else(|| Reply::status(500, json!({ "error": "no reply left" })));
+
+                let response = format!(
+                    "HTTP/1.1 {} Test\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    reply.status,
+                    reply.content_type,
+                    reply.body.len(),
+                    reply.body
+                );
+
+                stream.write_all(response.as_bytes()).await.unwrap();
+                stream.shutdown().await.ok();
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Request {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+
+    let head_end = loop {
+        let read = stream.read(&mut chunk).await.unwrap();
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let path = head.split_whitespace().nth(1).unwrap().to_string();
+    let length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().unwrap())
+        })
+        .unwrap_or(0);
+
+    while buffer.len() < head_end + length {
+        let read = stream.read(&mut chunk).await.unwrap();
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    Request {
+        path,
+        body: from_slice(&buffer[head_end..]).unwrap(),
+    }
+}
+
+async fn agent(backend: LocalBackend) -> Agent<LocalBackend> {
+    AgentBuilder::new()
+        .name("LocalBot".to_string())
+        .tool(Weather)
+        .build(
+            backend.with_retry_policy(RetryPolicy::none()),
+            MemoryEngine::in_memory(),
+        )
+        .await
+        .unwrap()
+}
+
+async fn say(agent: &mut Agent<LocalBackend>, message: &str) -> Result<String, MyselfError> {
+    let (_, output) = agent.interact_default(&message.to_string()).await?;
+
+    Ok(output.content)
+}
+
+async fn stream(agent: &mut Agent<LocalBackend>, message: &str) -> Vec<String> {
+    let interaction = agent.get_default_interaction().await.unwrap();
+
+    agent
+        .interact_stream(interaction.id, &message.to_string())
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await
+}
+
+async fn roles(agent: &mut Agent<LocalBackend>) -> Vec<InteractionBlockRole> {
+    let interaction = agent.get_default_interaction().await.unwrap();
+
+    agent
+        .memory_engine()
+        .unwrap()
+        .get_interaction_long_term_memory(interaction.id, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|block| block.role)
+        .collect()
+}
+
+fn openai_reply(message: Value, finish_reason: &str) -> Reply {
+    Reply::json(json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "model": "llama3",
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+        "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 },
+    }))
+}
+
+fn ollama_reply(message: Value) -> Reply {
+    Reply::json(json!({
+        "model": "llama3",
+        "message": message,
+        "done": true,
+        "done_reason": "stop",
+        "prompt_eval_count": 12,
+        "eval_count": 5,
+    }))
+}
+
+#[tokio::test]
+async fn openai_compatible_replies_are_stored_with_their_usage() {
+    let server = Server::start(vec![openai_reply(
+        json!({ "role": "assistant", "content": "Hello there." }),
+        "stop",
+    )])
+    .await;
+    let mut agent = agent(LocalBackend::openai_compatible(
+        format!("{}/v1", server.url),
+        "llama3",
+    ))
+    .await;
+
+    assert_eq!(say(&mut agent, "hello").await.unwrap(), "Hello there.");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].body["model"], "llama3");
+    assert_eq!(requests[0].body["stream"], false);
+    assert_eq!(
+        requests[0].body["tools"][0]["function"]["name"],
+        "get_weather"
+    );
+
+    let usage = agent.usage().await.unwrap();
+    assert_eq!(usage.responses(), 1);
+    assert_eq!(usage.prompt_tokens(), 12);
+    assert_eq!(usage.completion_tokens(), 5);
+}
+
+#[tokio::test]
+async fn ollama_replies_are_stored_with_their_usage() {
+    let server = Server::start(vec![ollama_reply(
+        json!({ "role": "assistant", "content": "Hello there." }),
+    )])
+    .await;
+    let mut agent = agent(LocalBackend::ollama(&server.url, "llama3")).await;
+
+    assert_eq!(say(&mut agent, "hello").await.unwrap(), "Hello there.");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/api/chat");
+    assert_eq!(requests[0].body["model"], "llama3");
+    assert_eq!(requests[0].body["stream"], false);
+    assert_eq!(requests[0].body["options"]["num_predict"], 1000);
+
+    let usage = agent.usage().await.unwrap();
+    assert_eq!(usage.responses(), 1);
+    assert_eq!(usage.prompt_tokens(), 12);
+    assert_eq!(usage.completion_tokens(), 5);
+}
+
+#[tokio::test]
+async fn openai_compatible_streams_are_read_from_server_sent_events() {
+    let chunk = |delta: Value, finish_reason: Value| {
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "model": "llama3",
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    };
+    let server = Server::start(vec![Reply::events(&[
+        chunk(json!({ "role": "assistant", "content": "" }), Value::Null),
+        chunk(json!({ "content": "Hello" }), Value::Null),
+        chunk(json!({ "content": " there." }), json!("stop")),
+        json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "model": "llama3",
+            "choices": [],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 },
+        }),
+    ])])
+    .await;
+    let mut agent = agent(LocalBackend::openai_compatible(
+        format!("{}/v1", server.url),
+        "llama3",
+    ))
+    .await;
+
+    assert_eq!(stream(&mut agent, "hello").await.concat(), "Hello there.");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].body["stream"], true);
+    assert_eq!(requests[0].body["stream_options"]["include_usage"], true);
+
+    let usage = agent.usage().await.unwrap();
+    assert_eq!(usage.responses(), 1);
+    assert_eq!(usage.prompt_tokens(), 12);
+    assert_eq!(usage.completion_tokens(), 5);
+}
+
+#[tokio::test]
+async fn ollama_streams_are_read_line_by_line() {
+    let line = |content: &str| {
+        json!({
+            "model": "llama3",
+            "message": { "role": "assistant", "content": content },
+            "done": false,
+        })
+    };
+    let server = Server::start(vec![Reply::lines(&[
+        line("Hello"),
+        line(" there."),
+        json!({
+            "model": "llama3",
+            "message": { "role": "assistant", "content": "" },
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 12,
+            "eval_count": 5,
+        }),
+    ])])
+    .await;
+    let mut agent = agent(LocalBackend::ollama(&server.url, "llama3")).await;
+
+    assert_eq!(stream(&mut agent, "hello").await.concat(), "Hello there.");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/api/chat");
+    assert_eq!(requests[0].body["stream"], true);
+
+    let usage = agent.usage().await.unwrap();
+    assert_eq!(usage.responses(), 1);
+    assert_eq!(usage.prompt_tokens(), 12);
+    assert_eq!(usage.completion_tokens(), 5);
+}
+
+#[tokio::test]
+async fn streams_keep_a_last_line_without_a_newline() {
+    let openai = [
+        json!({
+            "choices": [{ "index": 0, "delta": { "content": "Hello" }, "finish_reason": null }],
+        }),
+        json!({
+            "choices": [{ "index": 0, "delta": { "content": " there." }, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 },
+        }),
+    ];
+    let ollama = [
+        json!({
+            "model": "llama3",
+            "message": { "role": "assistant", "content": "Hello" },
+            "done": false,
+        }),
+        json!({
+            "model": "llama3",
+            "message": { "role": "assistant", "content": " there." },
+            "done": true,
+            "done_reason": "stop",
+            "prompt_eval_count": 12,
+            "eval_count": 5,
+        }),
+    ];
+
+    for (dialect, path, content_type, body) in [
+        (
+            LLMDialect::OpenAI,
+            "/v1",
+            "text/event-stream",
+            format!("data: {}\n\ndata: {}", openai[0], openai[1]),
+        ),
+        (
+            LLMDialect::Ollama,
+            "",
+            "application/x-ndjson",
+            format!("{}\n{}", ollama[0], ollama[1]),
+        ),
+    ] {
+        let server = Server::start(vec![Reply {
+            status: 200,
+            content_type,
+            body,
+        }])
+        .await;
+        let mut agent = agent(LocalBackend::new(
+            format!("{}{}", server.url, path),
+            dialect,
+            "llama3",
+        ))
+        .await;
+
+        assert_eq!(stream(&mut agent, "hello").await.concat(), "Hello there.");
+
+        let usage = agent.usage().await.unwrap();
+        assert_eq!(usage.prompt_tokens(), 12);
+        assert_eq!(usage.completion_tokens(), 5);
+    }
+}
+
+#[tokio::test]
+async fn history_transcripts_lose_their_oldest_lines_to_fit() {
+    let replies = (0..4)
+        .map(|_| openai_reply(json!({ "role": "assistant", "content": "Noted." }), "stop"))
+        .collect();
+    let server = Server::start(replies).await;
+    let mut agent = AgentBuilder::new()
+        .name("LocalBot".to_string())
+        .prompt_template(PromptTemplate::new().system("Transcript:\n{{history}}"))
+        .llm_configuration(LLMConfiguration {
+            max_tokens: 10,
+            context_window: Some(60),
+            ..Default::default()
+        })
+        .build(
+            LocalBackend::openai_compatible(format!("{}/v1", server.url), "llama3")
+                .with_retry_policy(RetryPolicy::none()),
+            MemoryEngine::in_memory(),
+        )
+        .await
+        .unwrap();
+
+    for message in [
+        "the first message",
+        "the second message",
+        "the third message",
+        "hello",
+    ] {
+        say(&mut agent, message).await.unwrap();
+    }
+
+    let requests = server.requests();
+    assert_eq!(requests[3].body["messages"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        requests[3].body["messages"][0]["content"],
+        "Transcript:\nUser (user): the second message\nLocalBot (agent): Noted.\nUser (user): the third message\nLocalBot (agent): Noted."
+    );
+}
+
+#[tokio::test]
+async fn openai_compatible_tool_calls_are_run_and_answered() {
+    let server = Server::start(vec![
+        openai_reply(
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": { "name": "get_weather", "arguments": "{\"city\": \"Lima\"}" },
+                }],
+            }),
+            "tool_calls",
+        ),
+        openai_reply(
+            json!({ "role": "assistant", "content": "It is sunny in Lima." }),
+            "stop",
+        ),
+    ])
+    .await;
+    let mut agent = agent(LocalBackend::openai_compatible(
+        format!("{}/v1", server.url),
+        "llama3",
+    ))
+    .await;
+
+    assert_eq!(
+        say(&mut agent, "What is the weather in Lima?")
+            .await
+            .unwrap(),
+        "It is sunny in Lima."
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+
+    let messages = requests[1].body["messages"].as_array().unwrap();
+    let call = &messages[messages.len() - 2];
+    let result = &messages[messages.len() - 1];
+    assert_eq!(call["tool_calls"][0]["id"], "call_1");
+    assert_eq!(
+        call["tool_calls"][0]["function"]["arguments"],
+        "{\"city\": \"Lima\"}"
+    );
+    assert_eq!(result["role"], "tool");
+    assert_eq!(result["tool_call_id"], "call_1");
+    assert_eq!(result["content"], "sunny in Lima");
+
+    assert!(matches!(
+        roles(&mut agent).await.as_slice(),
+        [
+            InteractionBlockRole::User,
+            InteractionBlockRole::ToolCall,
+            InteractionBlockRole::Tool,
+            InteractionBlockRole::Agent,
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn ollama_tool_calls_are_run_and_answered() {
+    let server = Server::start(vec![
+        ollama_reply(json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{
+                "function": { "name": "get_weather", "arguments": { "city": "Lima" } },
+            }],
+        })),
+        ollama_reply(json!({ "role": "assistant", "content": "It is sunny in Lima." })),
+    ])
+    .await;
+    let mut agent = agent(LocalBackend::ollama(&server.url, "llama3")).await;
+
+    assert_eq!(
+        say(&mut agent, "What is the weather in Lima?")
+            .await
+            .unwrap(),
+        "It is sunny in Lima."
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+
+    let messages = requests[1].body["messages"].as_array().unwrap();
+    let call = &messages[messages.len() - 2];
+    let result = &messages[messages.len() - 1];
+    assert_eq!(
+        call["tool_calls"][0]["function"]["arguments"],
+        json!({ "city": "Lima" })
+    );
+    assert_eq!(result["role"], "tool");
+    assert_eq!(result["content"], "sunny in Lima");
+
+    assert!(matches!(
+        roles(&mut agent).await.as_slice(),
+        [
+            InteractionBlockRole::User,
+            InteractionBlockRole::ToolCall,
+            InteractionBlockRole::Tool,
+            InteractionBlockRole::Agent,
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn error_bodies_are_returned_as_errors() {
+    for (dialect, path, error) in [
+        (
+            LLMDialect::OpenAI,
+            "/v1",
+            json!({ "message": "model 'llama3' not found", "type": "invalid_request_error" }),
+        ),
+        (LLMDialect::Ollama, "", json!("model 'llama3' not found")),
+    ] {
+        let server = Server::start(vec![Reply::status(404, json!({ "error": error }))]).await;
+        let mut agent = agent(LocalBackend::new(
+            format!("{}{}", server.url, path),
+            dialect,
+            "llama3",
+        ))
+        .await;
+
+        match say(&mut agent, "hello").await {
+            Err(MyselfError::Backend(LLMEngineError::InvalidResponse(message))) => {
+                assert_eq!(message, "404 Not Found: model 'llama3' not found")
+            }
+            other => panic!("expected a provider error, got {:?}", other),
+        }
+
+        assert_eq!(server.requests().len(), 1);
+        assert!(roles(&mut agent).await.is_empty());
+    }
+}
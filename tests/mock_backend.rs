@@ -0,0 +1,561 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+use myself::{
+    backend::{
+        core::AgentBackend,
+        mock::{MockBackend, MockReply, MockRequestKind, MockToolCall},
+    },
+    database::memory::MemoryEngine,
+    error::MyselfError,
+    llm::{LLMConfiguration, LLMEngineError},
+    sdk::{
+        agent::{Agent, AgentBuilder, SummarizationPolicy},
+        interaction::{InteractionBlock, InteractionBlockRole},
+        orchestration::Orchestration,
+        quota::Quota,
+        recall::{Hybrid, MemoryStrategy, SemanticTopK, SummaryWindow, TokenWindow},
+        tool::Tool,
+        usage::PriceTable,
+    },
+};
+
+struct Weather;
+
+#[async_trait]
+impl Tool for Weather {
+    fn name(&self) -> String {
+        "get_weather".to_string()
+    }
+
+    fn description(&self) -> String {
+        "Current weather of a city".to_string()
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city"],
+        })
+    }
+
+    async fn call(&self, arguments: Value) -> Result<String, MyselfError> {
+        Ok(format!("sunny in {}", arguments["city"].as_str().unwrap_or("?")))
+    }
+}
+
+async fn agent(backend: &MockBackend) -> Agent<MockBackend> {
+    AgentBuilder::new()
+        .name("MockBot".to_string())
+        .tool(Weather)
+        .max_tool_iterations(3)
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap()
+}
+
+async fn say(agent: &mut Agent<MockBackend>, message: &str) -> Result<String, MyselfError> {
+    let (_, output) = agent.interact_default(&message.to_string()).await?;
+
+    Ok(output.content)
+}
+
+#[tokio::test]
+async fn queued_replies_come_first_and_run_out() {
+    let backend = MockBackend::new().reply("first").reply("second");
+    let mut agent = agent(&backend).await;
+
+    assert_eq!(say(&mut agent, "hello").await.unwrap(), "first");
+    assert_eq!(say(&mut agent, "hello").await.unwrap(), "second");
+    assert!(matches!(
+        say(&mut agent, "hello").await,
+        Err(MyselfError::Backend(LLMEngineError::InvalidResponse(_)))
+    ));
+}
+
+#[tokio::test]
+async fn rules_match_the_latest_message() {
+    let backend = MockBackend::new()
+        .rule("weather", MockReply::Text("Always sunny.".to_string()))
+        .rule("bye", MockReply::Text("See you.".to_string()))
+        .responder(|_| MockReply::Text("Pardon?".to_string()));
+    let mut agent = agent(&backend).await;
+
+    assert_eq!(
+        say(&mut agent, "How is the weather?").await.unwrap(),
+        "Always sunny."
+    );
+    assert_eq!(say(&mut agent, "Something else").await.unwrap(), "Pardon?");
+    assert_eq!(say(&mut agent, "Ok, bye").await.unwrap(), "See you.");
+}
+
+#[tokio::test]
+async fn responder_sees_the_turn() {
+    let backend = MockBackend::new().responder(|turn| {
+        MockReply::Text(format!("{} said {}", turn.len(), turn[0].content))
+    });
+    let mut agent = agent(&backend).await;
+
+    assert_eq!(say(&mut agent, "hi").await.unwrap(), "1 said hi");
+}
+
+#[tokio::test]
+async fn requests_are_recorded() {
+    let backend = MockBackend::new().reply("one").reply("two");
+    let mut agent = agent(&backend).await;
+
+    say(&mut agent, "first question").await.unwrap();
+    say(&mut agent, "second question").await.unwrap();
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests
+        .iter()
+        .all(|request| matches!(request.kind, MockRequestKind::Response)));
+    assert_eq!(requests[1].blocks[0].content, "second question");
+    assert_eq!(requests[0].interaction.id, requests[1].interaction.id);
+
+    backend.clear_requests();
+    assert!(backend.requests().is_empty());
+}
+
+#[tokio::test]
+async fn injected_errors_fail_the_call_without_storing_it() {
+    let backend = MockBackend::new().fail("model is down").reply("back up");
+    let mut agent = agent(&backend).await;
+
+    match say(&mut agent, "hello").await {
+        Err(MyselfError::Backend(LLMEngineError::InvalidResponse(message))) => {
+            assert_eq!(message, "model is down")
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    assert_eq!(say(&mut agent, "hello again").await.unwrap(), "back up");
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let blocks = agent
+        .memory_engine()
+        .unwrap()
+        .get_interaction_long_term_memory(interaction.id, 10)
+        .await
+        .unwrap();
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].content, "hello again");
+}
+
+#[tokio::test]
+async fn latency_delays_every_call() {
+    let latency = Duration::from_millis(50);
+    let backend = MockBackend::new().reply("slow").latency(latency);
+    let mut agent = agent(&backend).await;
+
+    let started = Instant::now();
+    say(&mut agent, "hello").await.unwrap();
+
+    assert!(started.elapsed() >= latency);
+}
+
+#[tokio::test]
+async fn tool_calls_make_a_single_round_trip() {
+    let backend = MockBackend::new()
+        .rule(
+            "weather",
+            MockReply::ToolCalls(vec![MockToolCall::new(
+                "get_weather",
+                r#"{"city": "Lima"}"#,
+            )]),
+        )
+        .responder(|turn| {
+            MockReply::Text(format!(
+                "It is {}.",
+                turn.last().map(|block| block.content.as_str()).unwrap()
+            ))
+        });
+    let mut agent = agent(&backend).await;
+
+    assert_eq!(
+        say(&mut agent, "What is the weather in Lima?")
+            .await
+            .unwrap(),
+        "It is sunny in Lima."
+    );
+
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].blocks.len(), 3);
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let roles = agent
+        .memory_engine()
+        .unwrap()
+        .get_interaction_long_term_memory(interaction.id, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|block| block.role)
+        .collect::<Vec<_>>();
+
+    assert!(matches!(
+        roles.as_slice(),
+        [
+            InteractionBlockRole::User,
+            InteractionBlockRole::ToolCall,
+            InteractionBlockRole::Tool,
+            InteractionBlockRole::Agent,
+        ]
+    ));
+}
+
+#[tokio::test]
+async fn endless_tool_calls_stop_at_the_iteration_limit() {
+    let backend = MockBackend::new().responder(|_| {
+        MockReply::ToolCalls(vec![MockToolCall::new("get_weather", r#"{"city": "Lima"}"#)])
+    });
+    let mut agent = agent(&backend).await;
+
+    assert!(matches!(
+        say(&mut agent, "weather?").await,
+        Err(MyselfError::ToolIterationsExceeded(3))
+    ));
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let blocks = agent
+        .memory_engine()
+        .unwrap()
+        .get_interaction_long_term_memory(interaction.id, 20)
+        .await
+        .unwrap();
+    assert_eq!(blocks.len(), 9);
+    assert_eq!(blocks[0].content, "weather?");
+    assert_eq!(blocks[6].content, "sunny in Lima");
+    assert_eq!(blocks[8].content, "error: tool call limit of 3 reached");
+
+    assert_eq!(agent.usage().await.unwrap().responses(), 4);
+}
+
+#[tokio::test]
+async fn quotas_count_the_earlier_steps_of_a_turn() {
+    let backend = MockBackend::new()
+        .tool_calls(vec![MockToolCall::new("get_weather", r#"{"city": "Lima"}"#)])
+        .reply("It is sunny in Lima.");
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .tool(Weather)
+        .quota(Quota::unlimited().requests_per_minute(1))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        say(&mut agent, "What is the weather in Lima?").await,
+        Err(MyselfError::QuotaExceeded { .. })
+    ));
+    assert_eq!(backend.requests().len(), 1);
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let roles = agent
+        .memory_engine()
+        .unwrap()
+        .get_interaction_long_term_memory(interaction.id, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|block| block.role)
+        .collect::<Vec<_>>();
+
+    assert!(matches!(
+        roles.as_slice(),
+        [
+            InteractionBlockRole::User,
+            InteractionBlockRole::ToolCall,
+            InteractionBlockRole::Tool,
+        ]
+    ));
+
+    assert_eq!(agent.usage().await.unwrap().responses(), 1);
+}
+
+#[tokio::test]
+async fn used_up_quotas_stop_the_turn_before_summarizing() {
+    let backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .summarization(SummarizationPolicy::new(1, 0))
+        .quota(Quota::unlimited().requests_per_minute(1))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "first").await.unwrap();
+
+    assert!(matches!(
+        say(&mut agent, "second").await,
+        Err(MyselfError::QuotaExceeded { .. })
+    ));
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    assert!(matches!(
+        agent
+            .interact_stream(interaction.id, &"third".to_string())
+            .await,
+        Err(MyselfError::QuotaExceeded { .. })
+    ));
+
+    assert_eq!(backend.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn spend_limits_refuse_models_without_a_price() {
+    let backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .quota(Quota::unlimited().max_cost(1.0))
+        .prices(PriceTable::new().with_price("priced-model", 1.0, 1.0))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "first").await.unwrap();
+
+    match say(&mut agent, "second").await {
+        Err(MyselfError::Configuration(message)) => {
+            assert_eq!(message, "max_cost needs a price for model \"mock\"")
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(agent.allowance().await.is_err());
+    assert_eq!(backend.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn orchestrated_agents_are_billed_for_their_own_responses() {
+    let memory_engine = MemoryEngine::in_memory();
+
+    let mut agents = Vec::new();
+    for (name, reply) in [("Host", "Two words."), ("Guest", "Three words here.")] {
+        agents.push(
+            AgentBuilder::new()
+                .name(name.to_string())
+                .build(
+                    MockBackend::new().responder(move |_| MockReply::Text(reply.to_string())),
+                    memory_engine.clone(),
+                )
+                .await
+                .unwrap(),
+        );
+    }
+
+    let conversation = Orchestration::new(agents.clone())
+        .max_turns(3)
+        .start("Take turns.".to_string(), &"Start.".to_string())
+        .await
+        .unwrap();
+    assert_eq!(conversation.turns.len(), 3);
+
+    let host = agents[0].usage().await.unwrap();
+    assert_eq!(host.responses(), 2);
+    assert_eq!(host.completion_tokens(), 4);
+
+    let guest = agents[1].usage().await.unwrap();
+    assert_eq!(guest.responses(), 1);
+    assert_eq!(guest.completion_tokens(), 3);
+
+    let interaction = agents[0]
+        .get_interaction_usage(conversation.interaction_id)
+        .await
+        .unwrap();
+    assert_eq!(interaction.responses(), 3);
+}
+
+#[tokio::test]
+async fn streamed_responses_record_their_usage() {
+    let backend = MockBackend::new().reply("Streamed in four words.");
+    let mut agent = agent(&backend).await;
+    let interaction = agent.get_default_interaction().await.unwrap();
+
+    let deltas = agent
+        .interact_stream(interaction.id, &"Stream it.".to_string())
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(deltas.len(), 4);
+
+    let usage = agent.usage().await.unwrap();
+    assert_eq!(usage.responses(), 1);
+    assert_eq!(usage.completion_tokens(), 4);
+}
+
+#[tokio::test]
+async fn summaries_count_towards_usage() {
+    let backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .summarization(SummarizationPolicy::new(2, 0))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "first").await.unwrap();
+    say(&mut agent, "second").await.unwrap();
+    say(&mut agent, "third").await.unwrap();
+
+    let kinds = backend
+        .requests()
+        .into_iter()
+        .map(|request| request.kind)
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        kinds.as_slice(),
+        [
+            MockRequestKind::Response,
+            MockRequestKind::Response,
+            MockRequestKind::Summary,
+            MockRequestKind::Response,
+        ]
+    ));
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    assert!(!interaction.short_term_memory.is_empty());
+
+    assert_eq!(agent.usage().await.unwrap().responses(), 4);
+    assert_eq!(
+        agent
+            .get_interaction_usage(interaction.id)
+            .await
+            .unwrap()
+            .responses(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn summarized_blocks_are_left_out_of_the_history() {
+    let backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .summarization(SummarizationPolicy::new(2, 0))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "first").await.unwrap();
+    say(&mut agent, "second").await.unwrap();
+    say(&mut agent, "third").await.unwrap();
+
+    // The summary written before the third turn covers its first three blocks.
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let input = InteractionBlock::new_user(interaction.id, "fourth".to_string(), None);
+
+    for strategy in [
+        &SummaryWindow as &dyn MemoryStrategy<MockBackend>,
+        &Hybrid::new(2, 0.0),
+    ] {
+        let recall = strategy
+            .recall(&interaction, &input, &LLMConfiguration::default())
+            .await
+            .unwrap();
+
+        assert!(recall.summary.is_some());
+        assert_eq!(
+            recall
+                .history
+                .iter()
+                .map(|block| block.content.as_str())
+                .collect::<Vec<_>>(),
+            ["Noted.", "third", "Noted."]
+        );
+    }
+}
+
+#[tokio::test]
+async fn token_windows_count_blocks_as_chat_messages() {
+    let backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "first").await.unwrap();
+    say(&mut agent, "second").await.unwrap();
+
+    // The contents alone would fit, but each message also takes its role, name
+    // and framing.
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let input = InteractionBlock::new_user(interaction.id, "third".to_string(), None);
+    let recall = TokenWindow::new(10)
+        .recall(&interaction, &input, &LLMConfiguration::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        recall
+            .history
+            .iter()
+            .map(|block| block.content.as_str())
+            .collect::<Vec<_>>(),
+        ["Noted."]
+    );
+}
+
+#[tokio::test]
+async fn responses_recall_similar_messages_but_not_their_input() {
+    let mut backend = MockBackend::new().responder(|_| MockReply::Text("Noted.".to_string()));
+    let mut agent = AgentBuilder::new()
+        .name("MockBot".to_string())
+        .memory_strategy(SemanticTopK::new(1, -1.0))
+        .build(backend.clone(), MemoryEngine::in_memory())
+        .await
+        .unwrap();
+
+    say(&mut agent, "cats purr softly").await.unwrap();
+
+    let interaction = agent.get_default_interaction().await.unwrap();
+    let mut memory_engine = agent.memory_engine().unwrap();
+
+    // Stored without an embedding, as another agent would leave it.
+    memory_engine
+        .append_to_long_term_memory(
+            interaction.id,
+            &InteractionBlock::new_user(interaction.id, "do cats purr".to_string(), None),
+        )
+        .await
+        .unwrap();
+
+    agent.respond(interaction.id).await.unwrap();
+
+    let request = backend.requests().pop().unwrap();
+    assert!(request.blocks[0].embedding.is_some());
+
+    // Stored with an embedding, as the in-memory store keeps it.
+    let input = memory_engine
+        .append_to_long_term_memory(
+            interaction.id,
+            &InteractionBlock {
+                embedding: backend
+                    .embed(&["do cats purr again".to_string()])
+                    .await
+                    .unwrap()
+                    .pop(),
+                ..InteractionBlock::new_user(interaction.id, "do cats purr again".to_string(), None)
+            },
+        )
+        .await
+        .unwrap();
+
+    let relevant = interaction
+        .recall(&input, &LLMConfiguration::default())
+        .await
+        .unwrap()
+        .relevant
+        .into_iter()
+        .map(|block| block.content)
+        .collect::<Vec<_>>();
+    assert_eq!(relevant, ["cats purr softly"]);
+}